@@ -1,83 +1,556 @@
-use std::iter::{once, repeat};
+use std::{collections::HashMap, fmt};
 
 use itertools::Itertools;
 use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{char, digit1, line_ending},
-    combinator::{map, map_res, opt, recognize},
-    multi::many1,
-    sequence::{preceded, terminated, tuple},
+    bytes::complete::take_till1,
+    character::complete::{char, digit1, space1},
+    combinator::{all_consuming, map, map_res, opt, recognize, success},
+    sequence::{pair, preceded},
     IResult,
 };
 
-fn parse(input: &str) -> IResult<&str, Vec<(i32, i32)>> {
-    many1(terminated(
-        alt((
-            map(
-                preceded(
-                    tag("addx "),
-                    map_res(recognize(tuple((opt(char('-')), digit1))), |d| {
-                        i32::from_str_radix(d, 10)
-                    }),
-                ),
-                |x| (2, x),
-            ),
-            map(tag("noop"), |_| (1, 0)),
-        )),
-        opt(line_ending),
-    ))(input)
+use crate::ocr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Registers {
+    pub(crate) x: i32,
+    pub(crate) y: i32,
 }
 
-pub(crate) fn part1(input: &str) -> i32 {
-    let (_rest, effects) = parse(input).unwrap();
-    effects
+impl Default for Registers {
+    fn default() -> Self {
+        Registers { x: 1, y: 0 }
+    }
+}
+
+/// An instruction's argument as written in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operand<'a> {
+    None,
+    Int(i32),
+    #[cfg_attr(not(test), allow(dead_code))]
+    Label(&'a str),
+}
+
+pub(crate) fn no_operand(input: &str) -> IResult<&str, Operand<'_>> {
+    success(Operand::None)(input)
+}
+
+pub(crate) fn int_operand(input: &str) -> IResult<&str, Operand<'_>> {
+    preceded(
+        space1,
+        map(
+            map_res(recognize(pair(opt(char('-')), digit1)), |d: &str| d.parse()),
+            Operand::Int,
+        ),
+    )(input)
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn label_operand(input: &str) -> IResult<&str, Operand<'_>> {
+    preceded(space1, map(take_till1(char::is_whitespace), Operand::Label))(input)
+}
+
+/// Describes one kind of instruction.
+#[derive(Clone, Copy)]
+pub(crate) struct Spec {
+    pub(crate) mnemonic: &'static str,
+    /// Reads whatever follows the mnemonic.
+    pub(crate) operand: for<'a> fn(&'a str) -> IResult<&'a str, Operand<'a>>,
+    pub(crate) cycles: usize,
+    /// Applied at the end of the instruction's last cycle with the argument.
+    /// Labels are passed as the index of the instruction they point to.
+    /// Returns the index of the next instruction when it isn't the following
+    /// one.
+    pub(crate) effect: fn(&mut Registers, i32) -> Option<usize>,
+}
+
+/// The instructions from the puzzle.
+pub(crate) const BASIC: &[Spec] = &[
+    Spec {
+        mnemonic: "noop",
+        operand: no_operand,
+        cycles: 1,
+        effect: |_, _| None,
+    },
+    Spec {
+        mnemonic: "addx",
+        operand: int_operand,
+        cycles: 2,
+        effect: |r, v| {
+            r.x += v;
+            None
+        },
+    },
+];
+
+/// `BASIC` plus a few more to play with.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) const EXTENDED: &[Spec] = &[
+    BASIC[0],
+    BASIC[1],
+    Spec {
+        mnemonic: "subx",
+        operand: int_operand,
+        cycles: 2,
+        effect: |r, v| {
+            r.x -= v;
+            None
+        },
+    },
+    Spec {
+        mnemonic: "mulx",
+        operand: int_operand,
+        cycles: 4,
+        effect: |r, v| {
+            r.x *= v;
+            None
+        },
+    },
+    Spec {
+        mnemonic: "setx",
+        operand: int_operand,
+        cycles: 1,
+        effect: |r, v| {
+            r.x = v;
+            None
+        },
+    },
+    Spec {
+        mnemonic: "addy",
+        operand: int_operand,
+        cycles: 2,
+        effect: |r, v| {
+            r.y += v;
+            None
+        },
+    },
+    Spec {
+        mnemonic: "jmp",
+        operand: label_operand,
+        cycles: 1,
+        effect: |_, target| Some(target as usize),
+    },
+    // jump if x isn't zero
+    Spec {
+        mnemonic: "jnz",
+        operand: label_operand,
+        cycles: 1,
+        effect: |r, target| (r.x != 0).then_some(target as usize),
+    },
+];
+
+#[derive(Clone, Copy)]
+pub(crate) struct Instruction {
+    spec: Spec,
+    arg: i32,
+}
+
+impl fmt::Debug for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.spec.mnemonic, self.arg)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Error {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    BadOperand { line: usize },
+    UnknownLabel { line: usize, label: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {line}: unknown instruction {mnemonic:?}")
+            }
+            Error::BadOperand { line } => write!(f, "line {line}: bad operand"),
+            Error::UnknownLabel { line, label } => {
+                write!(f, "line {line}: unknown label {label:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Reads a program written with the instructions in `set`.
+///
+/// Each line is blank, an instruction, or a label like `loop:` naming the
+/// instruction after it.
+pub(crate) fn assemble(input: &str, set: &[Spec]) -> Result<Vec<Instruction>, Error> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    for (i, text) in input.lines().enumerate() {
+        let line = i + 1;
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if let Some(label) = text.strip_suffix(':') {
+            labels.insert(label, lines.len());
+            continue;
+        }
+        let mnemonic = text.split_whitespace().next().unwrap();
+        let spec =
+            set.iter()
+                .find(|s| s.mnemonic == mnemonic)
+                .ok_or_else(|| Error::UnknownMnemonic {
+                    line,
+                    mnemonic: mnemonic.to_owned(),
+                })?;
+        let (_, operand) = all_consuming(spec.operand)(&text[mnemonic.len()..])
+            .map_err(|_| Error::BadOperand { line })?;
+        lines.push((line, *spec, operand));
+    }
+
+    lines
         .into_iter()
-        .flat_map(|(dclock, dx)| {
-            repeat(0)
-                .take(if dclock > 1 { dclock as usize - 1 } else { 0 })
-                .chain(once(dx))
-        })
-        .enumerate()
-        .scan(1, |x, (i, dx)| {
-            let score = (*x) * (i + 1) as i32;
-            *x += dx;
-            Some(score)
+        .map(|(line, spec, operand)| {
+            let arg = match operand {
+                Operand::None => 0,
+                Operand::Int(v) => v,
+                Operand::Label(label) => *labels.get(label).ok_or_else(|| Error::UnknownLabel {
+                    line,
+                    label: label.to_owned(),
+                })? as i32,
+            };
+            Ok(Instruction { spec, arg })
         })
-        .skip(19)
-        .step_by(40)
+        .collect()
+}
+
+/// What the registers held during a (1-based) clock cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct State {
+    pub(crate) cycle: usize,
+    pub(crate) regs: Registers,
+}
+
+/// Where `Cpu::run` should stop.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) enum Breakpoint {
+    Cycle(usize),
+    When(fn(&Registers) -> bool),
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Breakpoint {
+    fn hit(&self, state: &State) -> bool {
+        match self {
+            &Breakpoint::Cycle(c) => state.cycle == c,
+            Breakpoint::When(cond) => cond(&state.regs),
+        }
+    }
+}
+
+pub(crate) struct Cpu {
+    program: Vec<Instruction>,
+    pc: usize,
+    /// cycles spent so far on the current instruction
+    busy: usize,
+    cycle: usize,
+    regs: Registers,
+}
+
+impl Cpu {
+    pub(crate) fn new(program: Vec<Instruction>) -> Self {
+        Cpu {
+            program,
+            pc: 0,
+            busy: 0,
+            cycle: 0,
+            regs: Registers::default(),
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn regs(&self) -> &Registers {
+        &self.regs
+    }
+
+    /// Runs one clock cycle and returns the state during it. `None` once the
+    /// program has finished.
+    pub(crate) fn step(&mut self) -> Option<State> {
+        let ins = *self.program.get(self.pc)?;
+        self.cycle += 1;
+        let during = State {
+            cycle: self.cycle,
+            regs: self.regs,
+        };
+        self.busy += 1;
+        if self.busy >= ins.spec.cycles {
+            let jump = (ins.spec.effect)(&mut self.regs, ins.arg);
+            self.pc = jump.unwrap_or(self.pc + 1);
+            self.busy = 0;
+        }
+        Some(during)
+    }
+
+    /// Steps until a cycle hits one of the breakpoints and returns that
+    /// cycle's state. Call again to carry on from there. `None` if the
+    /// program finished first.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn run(&mut self, breakpoints: &[Breakpoint]) -> Option<State> {
+        while let Some(state) = self.step() {
+            if breakpoints.iter().any(|b| b.hit(&state)) {
+                return Some(state);
+            }
+        }
+        None
+    }
+
+    /// Runs the rest of the program, recording every cycle. Never returns if
+    /// the program loops forever; use `run` or `take` for those.
+    pub(crate) fn trace(self) -> Trace {
+        Trace {
+            states: self.collect(),
+        }
+    }
+}
+
+impl Iterator for Cpu {
+    type Item = State;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.step()
+    }
+}
+
+pub(crate) struct Trace {
+    states: Vec<State>,
+}
+
+impl Trace {
+    /// Number of cycles recorded.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// The registers during a (1-based) cycle.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn during(&self, cycle: usize) -> Option<Registers> {
+        self.states.get(cycle.checked_sub(1)?).map(|s| s.regs)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &State> {
+        self.states.iter()
+    }
+}
+
+pub(crate) fn part1(input: &str) -> i32 {
+    let program = assemble(input, BASIC).unwrap();
+    let trace = Cpu::new(program).trace();
+    trace
+        .iter()
+        .filter(|s| s.cycle % 40 == 20)
+        .map(|s| s.cycle as i32 * s.regs.x)
         .sum()
 }
 
-pub(crate) fn part2(input: &str) {
-    let (_rest, effects) = parse(input).unwrap();
-    let im: Vec<_> = effects
-        .into_iter()
-        .flat_map(|(dclock, dx)| {
-            repeat(0)
-                .take(if dclock > 1 { dclock as usize - 1 } else { 0 })
-                .chain(once(dx))
+/// What the CRT shows. Pixels are lit or not.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Screen {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Screen {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Screen {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub(crate) fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[x + y * self.width]
+    }
+
+    pub(crate) fn set(&mut self, x: usize, y: usize, lit: bool) {
+        self.pixels[x + y * self.width] = lit;
+    }
+
+    /// Reads the letters drawn on the screen.
+    pub(crate) fn read(&self) -> Result<String, Vec<ocr::Unknown>> {
+        ocr::decode(self.width, |x, y| y < self.height && self.get(x, y))
+    }
+}
+
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.pixels.iter().chunks(self.width) {
+            let row: String = row.map(|&lit| if lit { '#' } else { '.' }).collect();
+            writeln!(f, "{row}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\n{self}")?;
+        match self.read() {
+            Ok(text) => write!(f, "reads {text:?}"),
+            Err(unknown) => write!(f, "{} unreadable glyphs", unknown.len()),
+        }
+    }
+}
+
+/// Runs the program while a `width` by `height` CRT draws a pixel per cycle.
+/// A pixel is lit when the 3 pixel wide sprite centered on X covers it. The
+/// beam wraps back to the top if the program runs long enough.
+pub(crate) fn render(input: &str, width: usize, height: usize) -> Screen {
+    let program = assemble(input, BASIC).unwrap();
+    let mut screen = Screen::new(width, height);
+    for State { cycle, regs } in Cpu::new(program) {
+        let i = (cycle - 1) % (width * height);
+        let (x, y) = (i % width, i / width);
+        screen.set(x, y, (regs.x - x as i32).abs() <= 1);
+    }
+    screen
+}
+
+pub(crate) fn part2(input: &str) -> Screen {
+    render(input, 40, 6)
+}
+
+#[test]
+fn day10() {
+    assert_eq!(13140, part1(include_str!("../assets/day10.test.txt")));
+    assert_eq!(
+        "\
+##..##..##..##..##..##..##..##..##..##..
+###...###...###...###...###...###...###.
+####....####....####....####....####....
+#####.....#####.....#####.....#####.....
+######......######......######......####
+#######.......#######.......#######.....
+",
+        part2(include_str!("../assets/day10.test.txt")).to_string()
+    );
+}
+
+#[test]
+fn day10_cpu() {
+    let program = assemble("noop\naddx 3\naddx -5\n", BASIC).unwrap();
+    let xs: Vec<_> = Cpu::new(program.clone()).map(|s| s.regs.x).collect();
+    assert_eq!(vec![1, 1, 1, 4, 4], xs);
+
+    let mut cpu = Cpu::new(program);
+    let bp = [Breakpoint::Cycle(2), Breakpoint::When(|r| r.x < 0)];
+    assert_eq!(
+        Some(State {
+            cycle: 2,
+            regs: Registers { x: 1, y: 0 }
+        }),
+        cpu.run(&bp)
+    );
+    assert_eq!(None, cpu.run(&bp));
+    assert_eq!(-1, cpu.regs().x);
+
+    let program = assemble(include_str!("../assets/day10.test.txt"), BASIC).unwrap();
+    let trace = Cpu::new(program.clone()).trace();
+    assert_eq!(21, trace.during(20).unwrap().x);
+    assert_eq!(18, trace.during(220).unwrap().x);
+    assert_eq!(None, trace.during(0));
+    assert_eq!(240, trace.len());
+
+    let mut cpu = Cpu::new(program);
+    let state = cpu.run(&[Breakpoint::When(|r| r.x > 30)]).unwrap();
+    assert_eq!(Some(state.regs), trace.during(state.cycle));
+}
+
+#[test]
+fn day10_instruction_set() {
+    // Expand the program by hand the way part1 used to and check the trace
+    // agrees cycle by cycle.
+    let input = include_str!("../assets/day10.test.txt");
+    let expected: Vec<_> = input
+        .lines()
+        .flat_map(|line| match line.strip_prefix("addx ") {
+            Some(v) => vec![0, v.parse().unwrap()],
+            None => vec![0],
         })
-        .enumerate()
-        .scan(1, |x, (i, dx)| {
-            let phase = (i % 40) as i32;
-            let out = if *x - 1 <= phase && phase <= *x + 1 {
-                '#'
-            } else {
-                ' '
-            };
+        .scan(1, |x, dx| {
+            let during = *x;
             *x += dx;
-            Some(out)
+            Some(during)
         })
         .collect();
-
-    for row in &im.into_iter().chunks(40) {
-        println!("{}", String::from_iter(row));
+    for set in [BASIC, EXTENDED] {
+        let xs: Vec<_> = Cpu::new(assemble(input, set).unwrap())
+            .map(|s| s.regs.x)
+            .collect();
+        assert_eq!(expected, xs);
     }
+
+    // count x down from 3, bumping y each time around
+    let program = assemble(
+        "setx 3\nloop:\n  addy 10\n  subx 1\n  jnz loop\nmulx 7\nend:\njmp end\n",
+        EXTENDED,
+    )
+    .unwrap();
+    let mut cpu = Cpu::new(program);
+    let state = cpu.run(&[Breakpoint::When(|r| r.y == 30)]).unwrap();
+    assert_eq!(14, state.cycle);
+    assert_eq!(Registers { x: 1, y: 30 }, state.regs);
+    // ends spinning on the last jump
+    assert_eq!(21, cpu.run(&[Breakpoint::Cycle(21)]).unwrap().cycle);
+    assert_eq!(Registers { x: 0, y: 30 }, *cpu.regs());
+    assert_eq!(1000, cpu.take(1000).count());
+
+    assert_eq!(
+        Some(Error::UnknownMnemonic {
+            line: 3,
+            mnemonic: "mulx".to_owned()
+        }),
+        assemble("noop\n\nmulx 2\n", BASIC).err()
+    );
+    assert_eq!(
+        Some(Error::BadOperand { line: 1 }),
+        assemble("addx\n", BASIC).err()
+    );
+    assert_eq!(
+        Some(Error::UnknownLabel {
+            line: 2,
+            label: "nowhere".to_owned()
+        }),
+        assemble("noop\njmp nowhere\n", EXTENDED).err()
+    );
 }
 
 #[test]
-fn day9() {
-    assert_eq!(13140, part1(include_str!("../assets/day10.test.txt")));
-    assert_eq!((), part2(include_str!("../assets/day10.test.txt")));
+fn day10_screen() {
+    // The sprite starts over the first three pixels, then jumps to x=5.
+    let program = "noop\nnoop\nnoop\nnoop\naddx 4\nnoop\nnoop\n";
+    assert_eq!("###...#.\n", render(program, 8, 1).to_string());
+
+    let mut screen = Screen::new(10, 6);
+    for (y, row) in [
+        "#..#.####",
+        "#..#.#...",
+        "####.###.",
+        "#..#.#...",
+        "#..#.#...",
+        "#..#.####",
+    ]
+    .iter()
+    .enumerate()
+    {
+        for (x, c) in row.chars().enumerate() {
+            screen.set(x, y, c == '#');
+        }
+    }
+    assert_eq!(Ok("HE".to_owned()), screen.read());
+
+    let screen = part2(include_str!("../assets/day10.test.txt"));
+    let unknown = screen.read().unwrap_err();
+    assert_eq!(8, unknown.len());
+    assert_eq!(ocr::Unknown { index: 3, x: 15 }, unknown[3]);
 }
@@ -1,43 +1,257 @@
-use std::collections::VecDeque;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
 
 use itertools::Itertools;
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{char, digit1, line_ending},
-    combinator::{map, map_res, opt},
-    multi::{many0, many1, separated_list0},
-    sequence::{preceded, terminated, tuple},
+    character::complete::{char, digit1, line_ending, one_of, space0},
+    combinator::{map, map_res, opt, value, verify},
+    multi::{fold_many0, many0, many1, separated_list0},
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
+use num_bigint::BigInt;
+use num_traits::Zero;
 
-enum Op {
-    Mul(isize),
-    Add(isize),
-    Square,
+/// Right-hand side of `new = ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Op {
+    Old,
+    Num(isize),
+    Add(Box<Op>, Box<Op>),
+    Sub(Box<Op>, Box<Op>),
+    Mul(Box<Op>, Box<Op>),
 }
 
 impl Op {
-    fn apply(&self, x: isize) -> isize {
+    /// Evaluates at `x`, or `None` if any step overflows.
+    fn apply(&self, x: isize) -> Option<isize> {
+        match self {
+            Op::Old => Some(x),
+            Op::Num(y) => Some(*y),
+            Op::Add(a, b) => a.apply(x)?.checked_add(b.apply(x)?),
+            Op::Sub(a, b) => a.apply(x)?.checked_sub(b.apply(x)?),
+            Op::Mul(a, b) => a.apply(x)?.checked_mul(b.apply(x)?),
+        }
+    }
+
+    fn apply_exact(&self, x: &BigInt) -> BigInt {
         match self {
-            Op::Mul(y) => y * x,
-            Op::Add(y) => y + x,
-            Op::Square => x * x,
+            Op::Old => x.clone(),
+            Op::Num(y) => BigInt::from(*y),
+            Op::Add(a, b) => a.apply_exact(x) + b.apply_exact(x),
+            Op::Sub(a, b) => a.apply_exact(x) - b.apply_exact(x),
+            Op::Mul(a, b) => a.apply_exact(x) * b.apply_exact(x),
         }
     }
+
+    /// Evaluates modulo `m`, reducing after every operation so that
+    /// intermediate values never grow past `m * m`. The result is in `0..m`.
+    fn apply_mod(&self, x: isize, m: isize) -> isize {
+        match self {
+            Op::Old => x.rem_euclid(m),
+            Op::Num(y) => y.rem_euclid(m),
+            Op::Add(a, b) => {
+                let s = a.apply_mod(x, m) as i128 + b.apply_mod(x, m) as i128;
+                (s % m as i128) as isize
+            }
+            Op::Sub(a, b) => (a.apply_mod(x, m) - b.apply_mod(x, m)).rem_euclid(m),
+            Op::Mul(a, b) => {
+                let p = a.apply_mod(x, m) as i128 * b.apply_mod(x, m) as i128;
+                (p % m as i128) as isize
+            }
+        }
+    }
+}
+
+/// Parses `+`, `-` and `*` over `old` and numbers, with the usual precedence,
+/// left associativity and parentheses.
+fn expr(input: &str) -> IResult<&str, Op> {
+    fn number(input: &str) -> IResult<&str, isize> {
+        map_res(digit1, |d: &str| d.parse())(input)
+    }
+
+    fn factor(input: &str) -> IResult<&str, Op> {
+        delimited(
+            space0,
+            alt((
+                value(Op::Old, tag("old")),
+                map(number, Op::Num),
+                delimited(char('('), expr, char(')')),
+            )),
+            space0,
+        )(input)
+    }
+
+    fn term(input: &str) -> IResult<&str, Op> {
+        let (input, first) = factor(input)?;
+        fold_many0(
+            preceded(char('*'), factor),
+            move || first.clone(),
+            |a, b| Op::Mul(Box::new(a), Box::new(b)),
+        )(input)
+    }
+
+    let (input, first) = term(input)?;
+    fold_many0(
+        pair(one_of("+-"), term),
+        move || first.clone(),
+        |a, (op, b)| match op {
+            '+' => Op::Add(Box::new(a), Box::new(b)),
+            _ => Op::Sub(Box::new(a), Box::new(b)),
+        },
+    )(input)
+}
+
+/// An item and how worried we are about it. Items are numbered in the
+/// order they're first listed.
+#[derive(Debug, Clone)]
+pub(crate) struct Item<W = isize> {
+    pub(crate) id: usize,
+    pub(crate) worry: W,
 }
 
-struct Monkey {
-    items: VecDeque<isize>,
+pub(crate) struct Monkey<W = isize> {
+    items: VecDeque<Item<W>>,
     op: Op,
     divisor: isize,
     next: [usize; 2],
     count: usize,
 }
 
+#[cfg_attr(not(test), allow(dead_code))]
+impl Monkey {
+    /// The same monkey, holding exact worry levels.
+    fn exact(self) -> Monkey<BigInt> {
+        Monkey {
+            items: self
+                .items
+                .into_iter()
+                .map(|Item { id, worry }| Item {
+                    id,
+                    worry: BigInt::from(worry),
+                })
+                .collect(),
+            op: self.op,
+            divisor: self.divisor,
+            next: self.next,
+            count: self.count,
+        }
+    }
+}
+
+/// How a worry level is stored.
+pub(crate) trait Worry: Clone + fmt::Display + From<isize> {
+    fn divisible_by(&self, divisor: isize) -> bool;
+}
+
+impl Worry for isize {
+    fn divisible_by(&self, divisor: isize) -> bool {
+        self % divisor == 0
+    }
+}
+
+impl Worry for BigInt {
+    fn divisible_by(&self, divisor: isize) -> bool {
+        (self % divisor).is_zero()
+    }
+}
+
+/// How worry levels calm down after each inspection.
+pub(crate) trait Relief<W> {
+    /// The worry level once `op` has inspected an item, or `None` if it
+    /// doesn't fit.
+    fn inspect(&self, op: &Op, worry: &W) -> Option<W>;
+}
+
+/// Worry is divided by `k`, rounding towards zero.
+pub(crate) struct Divide(pub(crate) isize);
+
+/// Worry is kept modulo a number every divisor divides, which leaves all the
+/// monkeys' tests unchanged.
+pub(crate) struct Modulo(pub(crate) isize);
+
+/// Worry only ever grows.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Unrelieved;
+
+impl Modulo {
+    /// The least common multiple of the divisors, or `None` if it doesn't fit
+    /// in an `isize`.
+    pub(crate) fn checked_lcm<W>(monkeys: &[Monkey<W>]) -> Option<Self> {
+        fn gcd(a: isize, b: isize) -> isize {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+        monkeys
+            .iter()
+            .try_fold(1isize, |l, m| {
+                (l / gcd(l, m.divisor)).checked_mul(m.divisor)
+            })
+            .map(Modulo)
+    }
+}
+
+impl Relief<isize> for Divide {
+    fn inspect(&self, op: &Op, worry: &isize) -> Option<isize> {
+        Some(op.apply(*worry)? / self.0)
+    }
+}
+
+impl Relief<BigInt> for Divide {
+    fn inspect(&self, op: &Op, worry: &BigInt) -> Option<BigInt> {
+        Some(op.apply_exact(worry) / self.0)
+    }
+}
+
+impl Relief<isize> for Modulo {
+    fn inspect(&self, op: &Op, worry: &isize) -> Option<isize> {
+        Some(op.apply_mod(*worry, self.0))
+    }
+}
+
+impl Relief<isize> for Unrelieved {
+    fn inspect(&self, op: &Op, worry: &isize) -> Option<isize> {
+        op.apply(*worry)
+    }
+}
+
+impl Relief<BigInt> for Unrelieved {
+    fn inspect(&self, op: &Op, worry: &BigInt) -> Option<BigInt> {
+        Some(op.apply_exact(worry))
+    }
+}
+
+/// An inspection whose worry level didn't fit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Overflow {
+    pub(crate) round: usize,
+    pub(crate) monkey: usize,
+    /// The worry level before the inspection.
+    pub(crate) worry: String,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "round {}: monkey {} overflowed inspecting an item of worry {}",
+            self.round, self.monkey, self.worry
+        )
+    }
+}
+
+impl std::error::Error for Overflow {}
+
 fn parse(input: &str) -> IResult<&str, Vec<Monkey>> {
     fn number(input: &str) -> IResult<&str, isize> {
-        map_res(digit1, |d| isize::from_str_radix(d, 10))(input)
+        map_res(digit1, |d: &str| d.parse())(input)
     }
     let monkey_title = tuple((tag("Monkey "), digit1, char(':'), line_ending));
     let starting_items = terminated(
@@ -47,7 +261,10 @@ fn parse(input: &str) -> IResult<&str, Vec<Monkey>> {
         ),
         line_ending,
     );
-    let divisor = terminated(preceded(tag("  Test: divisible by "), number), line_ending);
+    let divisor = terminated(
+        preceded(tag("  Test: divisible by "), verify(number, |&d| d > 0)),
+        line_ending,
+    );
     let on_true = terminated(
         preceded(tag("    If true: throw to monkey "), number),
         line_ending,
@@ -56,18 +273,8 @@ fn parse(input: &str) -> IResult<&str, Vec<Monkey>> {
         preceded(tag("    If false: throw to monkey "), number),
         opt(line_ending),
     );
-    let operation = terminated(
-        preceded(
-            tag("  Operation: new = old "),
-            alt((
-                map(tag("* old"), |_| Op::Square),
-                map(preceded(tag("* "), number), |v| Op::Mul(v)),
-                map(preceded(tag("+ "), number), |v| Op::Add(v)),
-            )),
-        ),
-        line_ending,
-    );
-    many1(terminated(
+    let operation = terminated(preceded(tag("  Operation: new = "), expr), line_ending);
+    let monkeys = many1(terminated(
         map(
             tuple((
                 monkey_title,
@@ -78,7 +285,10 @@ fn parse(input: &str) -> IResult<&str, Vec<Monkey>> {
                 on_false,
             )),
             |(_, items, op, divisor, t, f)| Monkey {
-                items: items.into(),
+                items: items
+                    .into_iter()
+                    .map(|worry| Item { id: 0, worry })
+                    .collect(),
                 op,
                 divisor,
                 next: [f as usize, t as usize],
@@ -86,52 +296,242 @@ fn parse(input: &str) -> IResult<&str, Vec<Monkey>> {
             },
         ),
         many0(line_ending),
-    ))(input)
+    ));
+    map(monkeys, |mut monkeys| {
+        let items = monkeys.iter_mut().flat_map(|m| m.items.iter_mut());
+        for (id, item) in items.enumerate() {
+            item.id = id;
+        }
+        monkeys
+    })(input)
 }
 
-pub(crate) fn part1(input: &str) -> usize {
-    let (_rest, mut monkeys) = parse(input).unwrap();
-    for _round in 0..20 {
-        for m in 0..monkeys.len() {
-            while let Some(worry) = monkeys[m].items.pop_front() {
-                let worry = monkeys[m].op.apply(worry) / 3;
-                let next = monkeys[m].next[((worry % monkeys[m].divisor) == 0) as usize];
-                monkeys[next].items.push_back(worry);
-                monkeys[m].count += 1;
-            }
+/// An item changing hands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Throw<W = isize> {
+    pub(crate) round: usize,
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    pub(crate) item: usize,
+    /// Worry level as the item flies.
+    pub(crate) worry: W,
+}
+
+/// Plays round number `round`, calming worry with `relief` after every
+/// inspection. Each monkey's `count` goes up by the items it inspected, and
+/// `on_throw` sees every throw.
+fn play_round<W: Worry, R: Relief<W>>(
+    monkeys: &mut [Monkey<W>],
+    round: usize,
+    relief: &R,
+    mut on_throw: impl FnMut(Throw<W>),
+) -> Result<(), Overflow> {
+    for m in 0..monkeys.len() {
+        while let Some(Item { id, worry }) = monkeys[m].items.pop_front() {
+            let worry = relief
+                .inspect(&monkeys[m].op, &worry)
+                .ok_or_else(|| Overflow {
+                    round,
+                    monkey: m,
+                    worry: worry.to_string(),
+                })?;
+            let next = monkeys[m].next[worry.divisible_by(monkeys[m].divisor) as usize];
+            on_throw(Throw {
+                round,
+                from: m,
+                to: next,
+                item: id,
+                worry: worry.clone(),
+            });
+            monkeys[next].items.push_back(Item { id, worry });
+            monkeys[m].count += 1;
         }
     }
-    monkeys
-        .into_iter()
-        .map(|m| m.count)
-        .sorted()
-        .rev()
-        .take(2)
-        .product::<usize>()
+    Ok(())
 }
 
-pub(crate) fn part2(input: &str) -> usize {
+/// Plays `rounds` rounds, see [`play_round`].
+fn play<W: Worry, R: Relief<W>>(
+    monkeys: &mut [Monkey<W>],
+    rounds: usize,
+    relief: &R,
+) -> Result<(), Overflow> {
+    for round in 1..=rounds {
+        play_round(monkeys, round, relief, |_| ())?;
+    }
+    Ok(())
+}
+
+/// Everything that happened over some rounds.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Log<W = isize> {
+    pub(crate) throws: Vec<Throw<W>>,
+    /// `counts[r][m]` is how many items monkey `m` had inspected after
+    /// round `r`, starting with round 0.
+    pub(crate) counts: Vec<Vec<usize>>,
+}
+
+/// Plays `rounds` rounds like [`play`], keeping track of what happens.
+#[cfg_attr(not(test), allow(dead_code))]
+fn record<W: Worry, R: Relief<W>>(
+    monkeys: &mut [Monkey<W>],
+    rounds: usize,
+    relief: &R,
+) -> Result<Log<W>, Overflow> {
+    let mut log = Log {
+        throws: Vec::new(),
+        counts: vec![counts(monkeys)],
+    };
+    for round in 1..=rounds {
+        play_round(monkeys, round, relief, |throw| log.throws.push(throw))?;
+        log.counts.push(counts(monkeys));
+    }
+    Ok(log)
+}
+
+/// Totals over a [`Log`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Summary {
+    /// Items inspected by each monkey.
+    pub(crate) inspections: Vec<usize>,
+    /// `throws[from][to]` counts the items `from` threw to `to`.
+    pub(crate) throws: Vec<Vec<usize>>,
+    /// Monkeys nobody ever threw anything to.
+    pub(crate) unreached: Vec<usize>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Summary {
+    /// Product of the `k` largest inspection counts.
+    pub(crate) fn monkey_business(&self, k: usize) -> usize {
+        monkey_business(&self.inspections, k)
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl<W> Log<W> {
+    pub(crate) fn summary(&self) -> Summary {
+        let n = self.counts[0].len();
+        let mut throws = vec![vec![0; n]; n];
+        for t in &self.throws {
+            throws[t.from][t.to] += 1;
+        }
+        let unreached = (0..n)
+            .filter(|&to| throws.iter().all(|row| row[to] == 0))
+            .collect();
+        // Inspections before the log started don't count.
+        let first = &self.counts[0];
+        let last = self.counts.last().unwrap();
+        Summary {
+            inspections: last.iter().zip(first).map(|(l, f)| l - f).collect(),
+            throws,
+            unreached,
+        }
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl<W: fmt::Display> Log<W> {
+    /// One line per throw, with a header.
+    pub(crate) fn to_csv(&self) -> String {
+        let mut csv = String::from("round,from,to,item,worry\n");
+        for t in &self.throws {
+            csv += &format!("{},{},{},{},{}\n", t.round, t.from, t.to, t.item, t.worry);
+        }
+        csv
+    }
+}
+
+fn counts<W>(monkeys: &[Monkey<W>]) -> Vec<usize> {
+    monkeys.iter().map(|m| m.count).collect()
+}
+
+pub(crate) fn part1(input: &str) -> usize {
     let (_rest, mut monkeys) = parse(input).unwrap();
+    play(&mut monkeys, 20, &Divide(3)).unwrap();
+    monkey_business(&counts(&monkeys), 2)
+}
 
-    let base: isize = monkeys.iter().map(|m| m.divisor).product();
+/// Same counts as [`play`] under [`Modulo`], but in time independent of `rounds`.
+///
+/// Items never affect each other under the modular rule: where an item goes
+/// depends only on which monkey holds it at the start of a round and its
+/// worry modulo the base. So each item is followed on its own until that
+/// pair repeats, and the rest of the rounds are whole laps of the cycle.
+/// `None` if the divisors' lcm doesn't fit in an `isize`.
+fn fast_forward(monkeys: &[Monkey], rounds: usize) -> Option<Vec<usize>> {
+    let Modulo(base) = Modulo::checked_lcm(monkeys)?;
+    let mut total = vec![0; monkeys.len()];
 
-    for _round in 0..10000 {
-        for m in 0..monkeys.len() {
-            while let Some(worry) = monkeys[m].items.pop_front() {
-                let worry = monkeys[m].op.apply(worry) % base;
+    let starts = monkeys.iter().enumerate().flat_map(|(m, monkey)| {
+        monkey
+            .items
+            .iter()
+            .map(move |item| (m, item.worry.rem_euclid(base)))
+    });
+    for mut state in starts {
+        // seen[state] is the round it was held at; counts[r] is what the
+        // item caused in the first r rounds.
+        let mut seen = HashMap::new();
+        let mut counts = vec![vec![0; monkeys.len()]];
+        let mut round = 0;
+        let lap = loop {
+            if round == rounds {
+                break None;
+            }
+            if let Some(start) = seen.insert(state, round) {
+                break Some(start);
+            }
+            let mut count = counts[round].clone();
+            let (mut m, mut worry) = state;
+            state = loop {
+                count[m] += 1;
+                worry = monkeys[m].op.apply_mod(worry, base);
                 let next = monkeys[m].next[((worry % monkeys[m].divisor) == 0) as usize];
-                monkeys[next].items.push_back(worry);
-                monkeys[m].count += 1;
+                // Lower numbered monkeys have had their turn already.
+                if next < m {
+                    break (next, worry);
+                }
+                m = next;
+            };
+            counts.push(count);
+            round += 1;
+        };
+
+        let end = match lap {
+            None => counts[rounds].clone(),
+            Some(start) => {
+                let len = round - start;
+                let (laps, rest) = ((rounds - start) / len, (rounds - start) % len);
+                (0..monkeys.len())
+                    .map(|m| {
+                        let per_lap = counts[round][m] - counts[start][m];
+                        counts[start + rest][m] + laps * per_lap
+                    })
+                    .collect()
             }
+        };
+        for (t, c) in total.iter_mut().zip(end) {
+            *t += c;
         }
     }
-    monkeys
-        .into_iter()
-        .map(|m| m.count)
-        .sorted()
-        .rev()
-        .take(2)
-        .product::<usize>()
+    Some(total)
+}
+
+fn monkey_business(counts: &[usize], k: usize) -> usize {
+    counts.iter().sorted().rev().take(k).product()
+}
+
+/// Monkey business after `rounds` rounds without relief, or `None` if the
+/// divisors' lcm doesn't fit in an `isize`.
+pub(crate) fn keep_away(input: &str, rounds: usize) -> Option<usize> {
+    let (_rest, monkeys) = parse(input).unwrap();
+    Some(monkey_business(&fast_forward(&monkeys, rounds)?, 2))
+}
+
+pub(crate) fn part2(input: &str) -> usize {
+    keep_away(input, 10000).unwrap()
 }
 
 #[test]
@@ -139,3 +539,212 @@ fn day11() {
     assert_eq!(10605, part1(include_str!("../assets/day11.test.txt")));
     assert_eq!(2713310158, part2(include_str!("../assets/day11.test.txt")));
 }
+
+#[test]
+fn day11_expr() {
+    let parse = |s| {
+        let (rest, op) = expr(s).unwrap();
+        assert_eq!("", rest, "{s}");
+        op
+    };
+    let cases = [
+        ("old * 19", 5, 95),
+        ("old + 6", 5, 11),
+        ("old * old", 5, 25),
+        ("old + old", 5, 10),
+        ("3 * old", 5, 15),
+        ("old - 7", 5, -2),
+        ("2 + old * 3", 5, 17),
+        ("(2 + old) * 3", 5, 21),
+        ("old - 1 - 2", 5, 2),
+        ("old * (old - 1) + (4)", 5, 24),
+    ];
+    for (s, x, y) in cases {
+        let op = parse(s);
+        assert_eq!(Some(y), op.apply(x), "{s}");
+        // Reducing first or last gives the same residue, whatever the sign.
+        for m in [2, 3, 7, 9699690] {
+            for x in [x, -x, 1000] {
+                let want = op.apply(x).unwrap().rem_euclid(m);
+                assert_eq!(want, op.apply_mod(x, m), "{s} at {x} mod {m}");
+                assert_eq!(want, op.apply_mod(x.rem_euclid(m), m), "{s} at {x} mod {m}");
+            }
+        }
+    }
+    // Products of residues this large only fit thanks to the wide multiply.
+    let m = 3037000499 * 3;
+    assert_eq!(1, parse("old * old").apply_mod(m - 1, m));
+    // And sums of residues past half of isize::MAX need the wide add.
+    let m = 6000000000000000000;
+    assert_eq!(m - 2, parse("old + old").apply_mod(m - 1, m));
+    let input = "\
+Monkey 0:
+  Starting items: 5999999999999999999
+  Operation: new = old + old
+  Test: divisible by 6000000000000000000
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items:\x20
+  Operation: new = old
+  Test: divisible by 2
+    If true: throw to monkey 0
+    If false: throw to monkey 0
+";
+    assert_eq!(Some(3 * 3), keep_away(input, 3));
+    assert!(expr("old /").map_or(true, |(rest, _)| !rest.is_empty()));
+}
+
+#[test]
+fn day11_fast_forward() {
+    let input = include_str!("../assets/day11.test.txt");
+    let (_rest, monkeys) = parse(input).unwrap();
+    for rounds in (0..200).chain([1000, 10000]) {
+        let (_rest, mut brute) = parse(input).unwrap();
+        play(&mut brute, rounds, &Modulo::checked_lcm(&monkeys).unwrap()).unwrap();
+        assert_eq!(
+            Some(counts(&brute)),
+            fast_forward(&monkeys, rounds),
+            "after {rounds} rounds"
+        );
+    }
+
+    // Every item settles into a loop, so inspections grow linearly.
+    let a = fast_forward(&monkeys, 1_000_000_000_000).unwrap();
+    let b = fast_forward(&monkeys, 2_000_000_000_000).unwrap();
+    for (a, b) in a.iter().zip(&b) {
+        assert!((a * 2).abs_diff(*b) < 1000, "{a} {b}");
+    }
+}
+
+#[test]
+fn day11_relief() {
+    let input = include_str!("../assets/day11.test.txt");
+    let monkeys = || parse(input).unwrap().1;
+
+    // Exact worry levels agree with the machine sized ones while they fit.
+    let mut small = monkeys();
+    play(&mut small, 20, &Divide(3)).unwrap();
+    let mut exact: Vec<_> = monkeys().into_iter().map(Monkey::exact).collect();
+    play(&mut exact, 20, &Divide(3)).unwrap();
+    assert_eq!(counts(&small), counts(&exact));
+
+    // Without relief the squaring monkey soon overflows an isize, but exact
+    // and modular worry still send every item the same way.
+    let mut small = monkeys();
+    let overflow = play(&mut small, 20, &Unrelieved).unwrap_err();
+    assert_eq!(2, overflow.monkey);
+    let mut exact: Vec<_> = monkeys().into_iter().map(Monkey::exact).collect();
+    play(&mut exact, 10, &Unrelieved).unwrap();
+    let mut modular = monkeys();
+    let relief = Modulo::checked_lcm(&modular).unwrap();
+    play(&mut modular, 10, &relief).unwrap();
+    assert_eq!(counts(&modular), counts(&exact));
+
+    let input = "\
+Monkey 0:
+  Starting items: 10, 20
+  Operation: new = old * old
+  Test: divisible by 6
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 3
+  Operation: new = old + 5
+  Test: divisible by 4
+    If true: throw to monkey 0
+    If false: throw to monkey 0
+";
+    let (_rest, mut modular) = parse(input).unwrap();
+    let relief = Modulo::checked_lcm(&modular).unwrap();
+    assert_eq!(12, relief.0);
+    play(&mut modular, 8, &relief).unwrap();
+    let (_rest, exact) = parse(input).unwrap();
+    let mut exact: Vec<_> = exact.into_iter().map(Monkey::exact).collect();
+    play(&mut exact, 8, &Unrelieved).unwrap();
+    assert_eq!(counts(&modular), counts(&exact));
+
+    // Zero divisors don't parse. Divisors whose lcm doesn't fit still play
+    // with exact worry, but leave no modulus to keep away in.
+    assert!(parse(&input.replace("by 6", "by 0")).is_err());
+    let coprime = input
+        .replace("by 6", "by 4294967291")
+        .replace("by 4", "by 4294967279");
+    let (_rest, monkeys) = parse(&coprime).unwrap();
+    assert!(Modulo::checked_lcm(&monkeys).is_none());
+    assert_eq!(None, keep_away(&coprime, 1));
+    let mut exact: Vec<_> = monkeys.into_iter().map(Monkey::exact).collect();
+    play(&mut exact, 20, &Divide(3)).unwrap();
+}
+
+#[test]
+fn day11_log() {
+    let input = include_str!("../assets/day11.test.txt");
+    let (_rest, mut monkeys) = parse(input).unwrap();
+    let log = record(&mut monkeys, 20, &Divide(3)).unwrap();
+    let summary = log.summary();
+    assert_eq!(10605, summary.monkey_business(2));
+    assert_eq!(101 * 105 * 95, summary.monkey_business(3));
+    assert_eq!(vec![101, 95, 7, 105], summary.inspections);
+    assert_eq!(counts(&monkeys), log.counts[20]);
+    assert_eq!(21, log.counts.len());
+    assert_eq!(vec![2, 4, 3, 5], log.counts[1]);
+    assert_eq!(Vec::<usize>::new(), summary.unreached);
+    for (m, row) in summary.throws.iter().enumerate() {
+        assert_eq!(summary.inspections[m], row.iter().sum::<usize>());
+    }
+    // Monkey 0 goes first, throwing item 0 to monkey 3.
+    assert_eq!(
+        Throw {
+            round: 1,
+            from: 0,
+            to: 3,
+            item: 0,
+            worry: 500
+        },
+        log.throws[0]
+    );
+    let csv = log.to_csv();
+    let mut lines = csv.lines();
+    assert_eq!(Some("round,from,to,item,worry"), lines.next());
+    assert_eq!(Some("1,0,3,0,500"), lines.next());
+    assert_eq!(Some("1,0,3,1,620"), lines.next());
+    assert_eq!(log.throws.len() - 2, lines.count());
+
+    let input = "\
+Monkey 0:
+  Starting items: 1
+  Operation: new = old
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items:\x20
+  Operation: new = old
+  Test: divisible by 2
+    If true: throw to monkey 0
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 2
+  Operation: new = old
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 0
+";
+    let (_rest, mut monkeys) = parse(input).unwrap();
+    let log = record(&mut monkeys, 3, &Unrelieved).unwrap();
+    assert_eq!(vec![2], log.summary().unreached);
+    assert_eq!(
+        vec![0, 1],
+        log.throws
+            .iter()
+            .map(|t| t.item)
+            .unique()
+            .sorted()
+            .collect_vec()
+    );
+}
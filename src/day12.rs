@@ -1,50 +1,137 @@
-use std::{cmp::Reverse, collections::BinaryHeap, iter::repeat};
+use std::{fmt, path::Path};
 
+use itertools::Itertools;
 use nom::{
     character::complete::{alpha1, line_ending},
-    combinator::{map, opt},
-    multi::many0_count,
+    combinator::{opt, verify},
+    multi::separated_list1,
     sequence::terminated,
     IResult,
 };
+use plotters::prelude::*;
+
+use crate::graph::{self, Paths};
+
+/// Which steps are allowed and what they cost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rules {
+    /// Most a step may climb.
+    pub(crate) max_ascent: u8,
+    /// Most a step may drop, or `None` for any drop.
+    pub(crate) max_descent: Option<u8>,
+    /// A step costs 1 plus this much per level climbed or dropped.
+    pub(crate) cost_per_level: usize,
+    /// Whether steps may also go diagonally.
+    pub(crate) diagonals: bool,
+}
+
+impl Rules {
+    /// Climb at most one level, drop any amount, one step at a time.
+    pub(crate) const PUZZLE: Rules = Rules {
+        max_ascent: 1,
+        max_descent: None,
+        cost_per_level: 0,
+        diagonals: false,
+    };
+
+    fn allows(&self, from: u8, to: u8) -> bool {
+        to <= from.saturating_add(self.max_ascent)
+            && self
+                .max_descent
+                .is_none_or(|d| from <= to.saturating_add(d))
+    }
+
+    fn cost(&self, from: u8, to: u8) -> usize {
+        1 + self.cost_per_level * from.abs_diff(to) as usize
+    }
+
+    /// Whether every step costs the same, so a breadth first search will do.
+    fn unit_cost(&self) -> bool {
+        self.cost_per_level == 0
+    }
+}
 
 struct Image {
     data: Vec<u8>,
     shape: (usize, usize),
-    stride: usize,
-    start: isize,
-    end: isize,
+    start: usize,
+    end: usize,
 }
 
 impl Image {
     fn nelem(&self) -> usize {
-        self.shape.1 * self.stride
+        self.shape.0 * self.shape.1
     }
 
-    fn as_pos(&self, i: isize) -> (isize, isize) {
-        (i % self.stride as isize, i / self.stride as isize)
+    fn as_pos(&self, i: usize) -> (usize, usize) {
+        (i % self.shape.0, i / self.shape.0)
     }
 
-    fn in_bounds(&self, i: isize) -> bool {
+    /// Cells next to `i`, in the order left, right, up, down, then up-left,
+    /// up-right, down-left and down-right if `diagonals` is set.
+    fn adjacent(&self, i: usize, diagonals: bool) -> impl Iterator<Item = usize> + '_ {
         let (x, y) = self.as_pos(i);
-        0 <= x && x < self.shape.0 as isize && 0 <= y && y < self.shape.1 as isize
+        let (w, h) = self.shape;
+        let n = if diagonals { 8 } else { 4 };
+        [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (1, -1),
+            (-1, 1),
+            (1, 1),
+        ][..n]
+            .iter()
+            .filter_map(move |&(dx, dy)| {
+                let x = x.checked_add_signed(dx).filter(|&x| x < w)?;
+                let y = y.checked_add_signed(dy).filter(|&y| y < h)?;
+                Some(x + y * w)
+            })
+    }
+
+    /// Cells a step from `i` can reach, with the cost of getting there.
+    fn moves<'a>(
+        &'a self,
+        i: usize,
+        rules: &'a Rules,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.adjacent(i, rules.diagonals)
+            .filter(move |&n| rules.allows(self.data[i], self.data[n]))
+            .map(move |n| (n, rules.cost(self.data[i], self.data[n])))
+    }
+
+    /// Cells that can reach `i` in a step, with the cost of doing so.
+    fn moves_into<'a>(
+        &'a self,
+        i: usize,
+        rules: &'a Rules,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.adjacent(i, rules.diagonals)
+            .filter(move |&n| rules.allows(self.data[n], self.data[i]))
+            .map(move |n| (n, rules.cost(self.data[n], self.data[i])))
     }
 }
 
 fn parse(input: &str) -> IResult<&str, Image> {
-    let (rest, width) = terminated(map(alpha1, |d: &str| d.len()), opt(line_ending))(input)?;
-    let (rest, height) = many0_count(terminated(alpha1, opt(line_ending)))(rest)?;
-    let mut data = input.as_bytes().to_owned();
+    let (rest, rows) = terminated(
+        verify(separated_list1(line_ending, alpha1), |rows: &Vec<&str>| {
+            rows.iter().all(|r| r.len() == rows[0].len())
+        }),
+        opt(line_ending),
+    )(input)?;
+    let mut data = rows.concat().into_bytes();
     let mut start = 0;
     let mut end = 0;
     for (i, d) in data.iter_mut().enumerate() {
         match *d {
             b'S' => {
-                start = i as isize;
+                start = i;
                 *d = b'a';
             }
             b'E' => {
-                end = i as isize;
+                end = i;
                 *d = b'z';
             }
             _ => {}
@@ -54,72 +141,221 @@ fn parse(input: &str) -> IResult<&str, Image> {
         rest,
         Image {
             data,
-            shape: (width, (height + 1)),
-            stride: width + 1,
+            shape: (rows[0].len(), rows.len()),
             start,
             end,
         },
     ))
 }
 
-pub(crate) fn part1(input: &str) -> usize {
-    let (_rest, im) = parse(input).unwrap();
-    let mut steps: Vec<usize> = vec![1 << 30; im.nelem()]; // set to something big
+/// Cheapest cost from the start to every cell.
+#[cfg_attr(not(test), allow(dead_code))]
+fn from_start(im: &Image, rules: &Rules) -> Paths {
+    if rules.unit_cost() {
+        graph::bfs(im.nelem(), [im.start], |cur| {
+            im.moves(cur, rules).map(|(n, _)| n)
+        })
+    } else {
+        graph::dijkstra(im.nelem(), [im.start], |cur| im.moves(cur, rules))
+    }
+}
 
-    // newline's (10) are much less than b'a' so paths can go down there, but
-    // they'll never come back up
+/// Cheapest cost from every cell to the end.
+fn to_end(im: &Image, rules: &Rules) -> Paths {
+    if rules.unit_cost() {
+        graph::bfs(im.nelem(), [im.end], |cur| {
+            im.moves_into(cur, rules).map(|(n, _)| n)
+        })
+    } else {
+        graph::dijkstra(im.nelem(), [im.end], |cur| im.moves_into(cur, rules))
+    }
+}
 
-    steps[im.start as usize] = 0;
-    let mut q: BinaryHeap<_> = repeat((Reverse(0), im.start as usize)).take(1).collect();
-    let deltas = [-1, 1, -(im.stride as isize), im.stride as isize];
-    while let Some((_, cur)) = q.pop() {
-        if cur == im.end as usize {
-            return steps[cur];
+/// A cost for every cell of the height map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Distances {
+    pub(crate) shape: (usize, usize),
+    dist: Vec<Option<usize>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Distances {
+    fn new(im: &Image, paths: &Paths) -> Self {
+        Distances {
+            shape: im.shape,
+            dist: paths.dists().to_vec(),
         }
+    }
 
-        for n in deltas
-            .iter()
-            .map(|d| cur as isize + d)
-            .filter(|&n| im.in_bounds(n))
-            .map(|n| n as usize)
-            .filter(|&n| im.data[n] <= im.data[cur] + 1)
-        {
-            if steps[cur] + 1 < steps[n] {
-                steps[n] = steps[cur] + 1;
-                q.push((Reverse(steps[n]), n));
-            }
+    /// `None` where there's no way through.
+    pub(crate) fn get(&self, x: usize, y: usize) -> Option<usize> {
+        self.dist[x + y * self.shape.0]
+    }
+}
+
+/// Right aligned columns, with `.` where there's no way through.
+impl fmt::Display for Distances {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cell = |d: &Option<usize>| d.map_or(".".to_owned(), |d| d.to_string());
+        let width = self.dist.iter().map(|d| cell(d).len()).max().unwrap_or(1);
+        for row in self.dist.chunks(self.shape.0) {
+            let row = row.iter().map(|d| format!("{:>width$}", cell(d))).join(" ");
+            writeln!(f, "{row}")?;
         }
+        Ok(())
     }
-    panic!("Couldn't reach destination!");
 }
 
-pub(crate) fn part2(input: &str) -> usize {
+/// How much it costs to get from `S` to each cell.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn distances_from_start(input: &str, rules: &Rules) -> Distances {
     let (_rest, im) = parse(input).unwrap();
-    let mut steps: Vec<usize> = vec![1 << 30; im.nelem()];
-    let mut mn = 1 << 30;
-
-    steps[im.end as usize] = 0;
-    let mut q: BinaryHeap<_> = repeat((Reverse(0), im.end as usize)).take(1).collect();
-    let deltas = [-1, 1, -(im.stride as isize), im.stride as isize];
-    while let Some((_, cur)) = q.pop() {
-        if im.data[cur] == b'a' {
-            mn = mn.min(steps[cur]);
-        }
+    Distances::new(&im, &from_start(&im, rules))
+}
 
-        for n in deltas
-            .iter()
-            .map(|d| cur as isize + d)
-            .filter(|&n| im.in_bounds(n))
-            .map(|n| n as usize)
-            .filter(|&n| im.data[n] + 1 >= im.data[cur])
-        {
-            if steps[cur] + 1 < steps[n] {
-                steps[n] = steps[cur] + 1;
-                q.push((Reverse(steps[n]), n));
-            }
+/// How much it costs to get from each cell to `E`.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn distances_to_end(input: &str, rules: &Rules) -> Distances {
+    let (_rest, im) = parse(input).unwrap();
+    Distances::new(&im, &to_end(&im, rules))
+}
+
+/// A cheapest way up the hill.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Route {
+    im: Image,
+    /// Cells visited as `(x, y)`, from the start to the end.
+    pub(crate) path: Vec<(usize, usize)>,
+    pub(crate) cost: usize,
+}
+
+/// Finds a cheapest route from `S` to `E`. Where there are several, each
+/// step takes the first direction in [`Image::adjacent`] order that stays
+/// on one.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn route(input: &str, rules: &Rules) -> Option<Route> {
+    let (_rest, im) = parse(input).unwrap();
+    let costs = to_end(&im, rules);
+    let mut cur = im.start;
+    let cost = costs.dist(cur)?;
+
+    let mut path = vec![cur];
+    while cur != im.end {
+        let left = costs.dist(cur);
+        cur = im
+            .moves(cur, rules)
+            .find(|&(n, c)| costs.dist(n).map(|d| d + c) == left)
+            .unwrap()
+            .0;
+        path.push(cur);
+    }
+    let path = path.into_iter().map(|i| im.as_pos(i)).collect();
+    Some(Route { im, path, cost })
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Route {
+    pub(crate) fn steps(&self) -> usize {
+        self.path.len() - 1
+    }
+
+    /// Draws the height map with the route over it, in `(0, 0)..(w, h)`
+    /// chart coordinates.
+    pub(crate) fn plot<P: AsRef<Path>>(&self, filename: P, title: &str) -> anyhow::Result<()> {
+        let (w, h) = self.im.shape;
+        let root = BitMapBackend::new(&filename, (1024, 768)).into_drawing_area();
+
+        root.fill(&WHITE)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 30))
+            .margin(5)
+            .top_x_label_area_size(40)
+            .y_label_area_size(40)
+            .build_cartesian_2d(0.0..w as f64, h as f64..0.0)?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .label_style(("sans-serif", 20))
+            .draw()?;
+
+        chart.draw_series((0..w).cartesian_product(0..h).map(|(x, y)| {
+            let v = self.im.data[x + y * w].saturating_sub(b'a') as f64 / 25.0;
+            let (x, y) = (x as f64, y as f64);
+            Rectangle::new(
+                [(x, y), (x + 1.0, y + 1.0)],
+                HSLColor(240.0 / 360.0 - 240.0 / 360.0 * v, 0.7, 0.1 + 0.4 * v).filled(),
+            )
+        }))?;
+
+        let center = |&(x, y): &(usize, usize)| (x as f64 + 0.5, y as f64 + 0.5);
+        chart.draw_series(LineSeries::new(
+            self.path.iter().map(center),
+            WHITE.stroke_width(3),
+        ))?;
+        chart.draw_series(
+            [self.path.first(), self.path.last()]
+                .into_iter()
+                .flatten()
+                .map(|p| Circle::new(center(p), 6, WHITE.filled())),
+        )?;
+
+        root.present()?;
+        Ok(())
+    }
+}
+
+/// Same as the puzzle's illustration: each cell on the route points to the
+/// next, the end is `E` and everything else is `.`. Diagonal steps are drawn
+/// as `/` or `\\`.
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (w, h) = self.im.shape;
+        let mut grid = vec![vec!['.'; w]; h];
+        for (&(x0, y0), &(x1, y1)) in self.path.iter().tuple_windows() {
+            grid[y0][x0] = match (x1 as isize - x0 as isize, y1 as isize - y0 as isize) {
+                (1, 0) => '>',
+                (-1, 0) => '<',
+                (0, 1) => 'v',
+                (0, -1) => '^',
+                (1, -1) | (-1, 1) => '/',
+                _ => '\\',
+            };
+        }
+        if let Some(&(x, y)) = self.path.last() {
+            grid[y][x] = 'E';
         }
+        for row in grid {
+            writeln!(f, "{}", String::from_iter(row))?;
+        }
+        Ok(())
     }
-    mn
+}
+
+pub(crate) fn part1(input: &str) -> usize {
+    let (_rest, im) = parse(input).unwrap();
+    let (steps, _path) = graph::astar(
+        im.nelem(),
+        im.start,
+        im.end,
+        |cur| im.moves(cur, &Rules::PUZZLE),
+        graph::manhattan(im.shape.0, im.end),
+    )
+    .expect("Couldn't reach destination!");
+    steps
+}
+
+pub(crate) fn part2(input: &str) -> usize {
+    let (_rest, im) = parse(input).unwrap();
+    let steps = to_end(&im, &Rules::PUZZLE);
+    (0..im.nelem())
+        .filter(|&i| im.data[i] == b'a')
+        .filter_map(|i| steps.dist(i))
+        .min()
+        .unwrap()
 }
 
 #[test]
@@ -127,3 +363,144 @@ fn day12() {
     assert_eq!(31, part1(include_str!("../assets/day12.test.txt")));
     assert_eq!(29, part2(include_str!("../assets/day12.test.txt")));
 }
+
+#[test]
+fn day12_route() {
+    assert!(route("SbcdE\n", &Rules::PUZZLE).is_none());
+
+    let found = route(include_str!("../assets/day12.test.txt"), &Rules::PUZZLE).unwrap();
+    assert_eq!(31, found.steps());
+    assert_eq!((0, 0), found.path[0]);
+    assert_eq!(Some(&(5, 2)), found.path.last());
+    assert_eq!(
+        "\
+>>vv<<<<
+..vvv<<^
+..vv>E^^
+..v>>>^^
+..>>>>>^
+",
+        found.to_string()
+    );
+
+    // The puzzle's own route renders exactly as illustrated.
+    let illustration = "\
+v..v<<<<
+>v.vv<<^
+.>vv>E^^
+..v>>>^^
+..>>>>>^
+";
+    let arrows: Vec<&[u8]> = illustration.lines().map(str::as_bytes).collect();
+    let (mut x, mut y) = (0, 0);
+    let mut path = vec![(x, y)];
+    while arrows[y][x] != b'E' {
+        match arrows[y][x] {
+            b'>' => x += 1,
+            b'<' => x -= 1,
+            b'v' => y += 1,
+            _ => y -= 1,
+        }
+        path.push((x, y));
+    }
+    let illustrated = Route { path, ..found };
+    assert_eq!(31, illustrated.steps());
+    assert_eq!(illustration, illustrated.to_string());
+}
+
+#[test]
+fn day12_rules() {
+    let input = include_str!("../assets/day12.test.txt");
+    let puzzle = Rules::PUZZLE;
+    let from = distances_from_start(input, &puzzle);
+    let to = distances_to_end(input, &puzzle);
+    assert_eq!(Some(31), from.get(5, 2));
+    assert_eq!(Some(31), to.get(0, 0));
+    assert_eq!(Some(0), from.get(0, 0));
+    assert_eq!(Some(0), to.get(5, 2));
+    assert_eq!(
+        "\
+31 30 29 12 13 14 15 16
+30 29 28 11  2  3  4 17
+31 28 27 10  1  0  5 18
+30 27 26  9  8  7  6 19
+29 28 25 24 23 22 21 20
+",
+        to.to_string()
+    );
+
+    // Any drop is at most 25 levels.
+    let any_drop = Rules {
+        max_descent: Some(25),
+        ..puzzle
+    };
+    assert_eq!(to, distances_to_end(input, &any_drop));
+
+    // Straight up the alphabet.
+    let ramp = "SbcdefghijklmnopqrstuvwxyE";
+    let costly = Rules {
+        cost_per_level: 2,
+        ..puzzle
+    };
+    assert_eq!(25, route(ramp, &puzzle).unwrap().cost);
+    assert_eq!(75, route(ramp, &costly).unwrap().cost);
+    let flat = Rules {
+        max_ascent: 0,
+        ..puzzle
+    };
+    assert!(route(ramp, &flat).is_none());
+
+    // Over a ridge and down the other side.
+    let ridge = "SbcbaaE";
+    let gentle = Rules {
+        max_descent: Some(0),
+        ..puzzle
+    };
+    let from = distances_from_start(ridge, &puzzle);
+    assert_eq!("0 1 2 3 4 5 .\n", from.to_string());
+    let from = distances_from_start(ridge, &gentle);
+    assert_eq!("0 1 2 . . . .\n", from.to_string());
+
+    // Diagonals cut corners.
+    let plain = "Sbb\nbbb\nbbE\n";
+    let steep = Rules {
+        max_ascent: 25,
+        ..puzzle
+    };
+    let diagonal = Rules {
+        diagonals: true,
+        ..steep
+    };
+    assert_eq!(4, route(plain, &steep).unwrap().cost);
+    let found = route(plain, &diagonal).unwrap();
+    assert_eq!(2, found.cost);
+    assert_eq!("\\..\n.\\.\n..E\n", found.to_string());
+    assert!(
+        route(
+            input,
+            &Rules {
+                diagonals: true,
+                ..puzzle
+            }
+        )
+        .unwrap()
+        .cost
+            <= 31
+    );
+}
+
+#[test]
+fn day12_plot() {
+    let path = crate::testing::scratch_dir("day12_plot").join("route.png");
+    route(include_str!("../assets/day12.test.txt"), &Rules::PUZZLE)
+        .unwrap()
+        .plot(&path, "Route")
+        .unwrap();
+    assert!(path.metadata().unwrap().len() > 0);
+
+    // Uppercase heights are below `a` and drawn as the lowest.
+    let cliffs = format!("Sbcdefghijklmnopqrstuvwxyz\n{}E\n", "A".repeat(25));
+    let route = route(&cliffs, &Rules::PUZZLE).unwrap();
+    assert_eq!(26, route.steps());
+    route.plot(&path, "Cliffs").unwrap();
+}
@@ -1,15 +1,21 @@
-use std::cmp::Ordering;
+use std::{cmp::Ordering, fmt, str::FromStr};
 
 use itertools::Itertools;
+use num_bigint::BigUint;
+use serde_json::Value;
 
-#[derive(PartialEq, Eq)]
-enum Item {
-    Num(u8),
-    List(Vec<Item>),
+/// A distress signal packet: an integer of any size, or a list of packets.
+///
+/// Packets are ordered the way the puzzle compares them, and equality
+/// follows suit, so `2 == [2] == [[2]]`.
+#[derive(Debug, Clone)]
+pub(crate) enum Packet {
+    Num(BigUint),
+    List(Vec<Packet>),
 }
 
 struct Document {
-    pairs: Vec<(Item, Item)>,
+    pairs: Vec<(Packet, Packet)>,
 }
 
 mod parse {
@@ -17,25 +23,25 @@ mod parse {
         branch::alt,
         character::complete::{char, digit1, line_ending},
         combinator::{map, map_res, opt},
-        multi::{many0, many1, separated_list0, separated_list1},
+        multi::{many0, many1, separated_list0},
         sequence::{delimited, terminated, tuple},
         IResult,
     };
 
-    use super::{Document, Item};
+    use super::{Document, Packet};
 
-    fn number(input: &str) -> IResult<&str, u8> {
-        map_res(digit1, |s| u8::from_str_radix(s, 10))(input)
+    fn number(input: &str) -> IResult<&str, Packet> {
+        map_res(digit1, |s: &str| s.parse().map(Packet::Num))(input)
     }
 
-    fn list(input: &str) -> IResult<&str, Item> {
+    pub(super) fn list(input: &str) -> IResult<&str, Packet> {
         map(
             delimited(
                 char('['),
-                separated_list0(char(','), alt((map(number, |x| Item::Num(x)), list))),
+                separated_list0(char(','), alt((number, list))),
                 char(']'),
             ),
-            |items| Item::List(items),
+            Packet::List,
         )(input)
     }
 
@@ -51,33 +57,276 @@ mod parse {
             |pairs| Document { pairs },
         )(input)
     }
+}
+
+/// Which packet of a pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Side {
+    Left,
+    Right,
+}
 
-    pub(super) fn part2(input: &str) -> IResult<&str, Vec<Item>> {
-        separated_list1(many1(line_ending), list)(input)
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        })
     }
 }
 
-fn cmp(left: &Item, right: &Item) -> Ordering {
-    match (left, right) {
-        (&Item::Num(x), y @ Item::List(_)) => cmp(&Item::List(vec![Item::Num(x)]), y),
-        (x @ Item::List(_), &Item::Num(y)) => cmp(x, &Item::List(vec![Item::Num(y)])),
-        (Item::Num(x), Item::Num(y)) => {
-            if x < y {
-                Ordering::Less
-            } else if x > y {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
+/// Something being compared: a packet, or the items of a list.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Operand<'a> {
+    Packet(&'a Packet),
+    Items(&'a [Packet]),
+}
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Packet(p) => write!(f, "{p}"),
+            Operand::Items(items) => write!(f, "[{}]", items.iter().join(",")),
+        }
+    }
+}
+
+/// What [`cmp`] did at some point, nested `depth` comparisons deep.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Step<'a> {
+    pub(crate) depth: usize,
+    pub(crate) event: Event<'a>,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) enum Event<'a> {
+    Compare {
+        left: Operand<'a>,
+        right: Operand<'a>,
+    },
+    /// The integer on `side` is wrapped in a list to match the other side.
+    Convert { side: Side, value: &'a Packet },
+    /// Decides the comparison.
+    Smaller(Side),
+    /// Decides the comparison.
+    RanOut(Side),
+}
+
+/// Compares like [`Ord`], telling `log` about every step on the way.
+fn cmp<'a>(
+    left: &'a Packet,
+    right: &'a Packet,
+    depth: usize,
+    log: &mut impl FnMut(Step<'a>),
+) -> Ordering {
+    let step = |event| Step { depth, event };
+    log(step(Event::Compare {
+        left: Operand::Packet(left),
+        right: Operand::Packet(right),
+    }));
+    let (x, y, side) = match (left, right) {
+        (Packet::Num(x), Packet::Num(y)) => {
+            let c = x.cmp(y);
+            match c {
+                Ordering::Less => log(step(Event::Smaller(Side::Left)).deeper()),
+                Ordering::Greater => log(step(Event::Smaller(Side::Right)).deeper()),
+                Ordering::Equal => {}
             }
+            return c;
         }
-        (Item::List(x), Item::List(y)) => {
-            for i in 0..x.len().min(y.len()) {
-                let c = cmp(&x[i], &y[i]);
-                if c != Ordering::Equal {
-                    return c;
+        (Packet::List(x), Packet::List(y)) => return cmp_lists(x, y, depth, log),
+        (Packet::Num(_), Packet::List(y)) => (std::slice::from_ref(left), &y[..], Side::Left),
+        (Packet::List(x), Packet::Num(_)) => (&x[..], std::slice::from_ref(right), Side::Right),
+    };
+    let value = if side == Side::Left { left } else { right };
+    log(step(Event::Convert { side, value }).deeper());
+    log(step(Event::Compare {
+        left: Operand::Items(x),
+        right: Operand::Items(y),
+    })
+    .deeper());
+    cmp_lists(x, y, depth + 1, log)
+}
+
+/// Compares the items of two lists, which are `depth` deep.
+fn cmp_lists<'a>(
+    x: &'a [Packet],
+    y: &'a [Packet],
+    depth: usize,
+    log: &mut impl FnMut(Step<'a>),
+) -> Ordering {
+    for (a, b) in x.iter().zip(y) {
+        let c = cmp(a, b, depth + 1, log);
+        if c != Ordering::Equal {
+            return c;
+        }
+    }
+    let c = x.len().cmp(&y.len());
+    let side = match c {
+        Ordering::Less => Side::Left,
+        Ordering::Greater => Side::Right,
+        Ordering::Equal => return c,
+    };
+    log(Step {
+        depth: depth + 1,
+        event: Event::RanOut(side),
+    });
+    c
+}
+
+impl Step<'_> {
+    fn deeper(self) -> Self {
+        Step {
+            depth: self.depth + 1,
+            ..self
+        }
+    }
+}
+
+/// Why a pair of packets compares the way it does.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Explanation<'a> {
+    pub(crate) steps: Vec<Step<'a>>,
+    pub(crate) order: Ordering,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn explain<'a>(left: &'a Packet, right: &'a Packet) -> Explanation<'a> {
+    let mut steps = Vec::new();
+    let order = cmp(left, right, 0, &mut |step| steps.push(step));
+    Explanation { steps, order }
+}
+
+/// In the puzzle's words, one indented line per step.
+impl fmt::Display for Explanation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = |side| match side {
+            Side::Left => "so inputs are in the right order",
+            Side::Right => "so inputs are not in the right order",
+        };
+        let capital = |side| match side {
+            Side::Left => "Left",
+            Side::Right => "Right",
+        };
+        for Step { depth, event } in &self.steps {
+            write!(f, "{:indent$}- ", "", indent = 2 * depth)?;
+            match event {
+                Event::Compare { left, right } => writeln!(f, "Compare {left} vs {right}")?,
+                Event::Convert { side, value } => writeln!(
+                    f,
+                    "Mixed types; convert {side} to [{value}] and retry comparison"
+                )?,
+                &Event::Smaller(side) => {
+                    writeln!(f, "{} side is smaller, {}", capital(side), verdict(side))?
                 }
+                &Event::RanOut(side) => writeln!(
+                    f,
+                    "{} side ran out of items, {}",
+                    capital(side),
+                    verdict(side)
+                )?,
             }
-            cmp(&Item::Num(x.len() as u8), &Item::Num(y.len() as u8))
+        }
+        Ok(())
+    }
+}
+
+/// Explains every pair that's out of order, by its 1-based index.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn misordered(input: &str) -> Vec<(usize, String)> {
+    let (_rest, doc) = parse::part1(input).unwrap();
+    doc.pairs
+        .iter()
+        .enumerate()
+        .map(|(i, (left, right))| (i + 1, explain(left, right)))
+        .filter(|(_, e)| e.order == Ordering::Greater)
+        .map(|(i, e)| (i, e.to_string()))
+        .collect()
+}
+
+impl Ord for Packet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp(self, other, 0, &mut |_| ())
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Packet {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Packet {}
+
+/// Writes the packet the way the input does.
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Packet::Num(x) => write!(f, "{x}"),
+            Packet::List(items) => write!(f, "[{}]", items.iter().join(",")),
+        }
+    }
+}
+
+/// Text or JSON that isn't a packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NotAPacket(pub(crate) String);
+
+impl fmt::Display for NotAPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not a packet: {}", self.0)
+    }
+}
+
+impl std::error::Error for NotAPacket {}
+
+impl FromStr for Packet {
+    type Err = NotAPacket;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse::list(s) {
+            Ok(("", packet)) => Ok(packet),
+            _ => Err(NotAPacket(s.to_owned())),
+        }
+    }
+}
+
+impl From<&Packet> for Value {
+    fn from(packet: &Packet) -> Self {
+        match packet {
+            // Exact for any size thanks to serde_json's `arbitrary_precision`.
+            Packet::Num(x) => Value::Number(x.to_string().parse().unwrap()),
+            Packet::List(items) => Value::Array(items.iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Packet {
+    type Error = NotAPacket;
+
+    /// Takes arrays of arrays and non-negative integers.
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(x) => x
+                .to_string()
+                .parse()
+                .map(Packet::Num)
+                .map_err(|_| NotAPacket(value.to_string())),
+            Value::Array(items) => items
+                .iter()
+                .map(Packet::try_from)
+                .collect::<Result<_, _>>()
+                .map(Packet::List),
+            _ => Err(NotAPacket(value.to_string())),
         }
     }
 }
@@ -87,34 +336,45 @@ pub(crate) fn part1(input: &str) -> usize {
     doc.pairs
         .into_iter()
         .enumerate()
-        .filter_map(|(i, (left, right))| {
-            if cmp(&left, &right) == Ordering::Less {
-                Some(i + 1)
-            } else {
-                None
-            }
-        })
+        .filter_map(|(i, (left, right))| if left < right { Some(i + 1) } else { None })
         .sum()
 }
 
+/// Reads packets one line at a time, skipping blank lines.
+pub(crate) fn packets(input: &str) -> impl Iterator<Item = Result<Packet, NotAPacket>> + '_ {
+    input.lines().filter(|l| !l.is_empty()).map(str::parse)
+}
+
+/// Where each probe would land, counting from 1, if it were sorted in with
+/// the other probes and `packets`. That's one plus everything smaller than
+/// it; probes that are equal keep their order, and packets equal to a probe
+/// go after it.
+///
+/// Reads `packets` once, without keeping them.
+pub(crate) fn ranks(packets: impl IntoIterator<Item = Packet>, probes: &[Packet]) -> Vec<usize> {
+    let order: Vec<usize> = (0..probes.len())
+        .sorted_by(|&a, &b| probes[a].cmp(&probes[b]))
+        .collect();
+    // below[j] counts the packets smaller than the j-th smallest probe, but
+    // not the one before it.
+    let mut below = vec![0; probes.len() + 1];
+    for packet in packets {
+        below[order.partition_point(|&i| probes[i] <= packet)] += 1;
+    }
+    let mut ranks = vec![0; probes.len()];
+    let mut smaller = 0;
+    for (j, &i) in order.iter().enumerate() {
+        smaller += below[j];
+        ranks[i] = 1 + smaller + j;
+    }
+    ranks
+}
+
 pub(crate) fn part2(input: &str) -> usize {
-    let (_rest, doc) = parse::part2(input).unwrap();
-    let dividers = [
-        Item::List(vec![Item::List(vec![Item::Num(2)])]),
-        Item::List(vec![Item::List(vec![Item::Num(6)])]),
-    ];
-    doc.iter()
-        .chain(dividers[..].into_iter())
-        .sorted_by(|&a, &b| cmp(a, b))
-        .enumerate()
-        .filter_map(|(i, e)| {
-            if *e == dividers[0] || *e == dividers[1] {
-                Some(i + 1)
-            } else {
-                None
-            }
-        })
-        .product::<usize>()
+    let dividers = ["[[2]]", "[[6]]"].map(|d| d.parse().unwrap());
+    ranks(packets(input).map(Result::unwrap), &dividers)
+        .into_iter()
+        .product()
 }
 
 #[test]
@@ -122,3 +382,149 @@ fn day13() {
     assert_eq!(13, part1(include_str!("../assets/day13.test.txt")));
     assert_eq!(140, part2(include_str!("../assets/day13.test.txt")));
 }
+
+#[test]
+fn day13_packets() {
+    let input = include_str!("../assets/day13.test.txt");
+    for line in input.lines().filter(|l| !l.is_empty()) {
+        let packet: Packet = line.parse().unwrap();
+        assert_eq!(line, packet.to_string());
+        let json = Value::from(&packet);
+        assert_eq!(json, serde_json::from_str::<Value>(line).unwrap());
+        assert_eq!(line, Packet::try_from(&json).unwrap().to_string());
+    }
+
+    let p = |s: &str| s.parse::<Packet>().unwrap();
+    // Past the range of small integers and long lists.
+    assert!(p("[255]") < p("[256]"));
+    assert!(p("[99999999999999999999999999999]") < p("[100000000000000000000000000000]"));
+    let long = |n| p(&format!("[{}]", vec!["1"; n].join(",")));
+    assert!(long(256) < long(257));
+    assert!(long(300) > long(44));
+
+    assert_eq!(p("[2]"), p("[[2]]"));
+    assert_eq!(Ordering::Equal, p("[[2]]").cmp(&p("[2]")));
+    assert_ne!(p("[2]"), p("[2,2]"));
+    let sorted: Vec<_> = ["[3]", "[[1],2]", "[]", "[1,[3]]"]
+        .map(p)
+        .into_iter()
+        .sorted()
+        .collect();
+    assert_eq!("[] [[1],2] [1,[3]] [3]", sorted.iter().join(" "));
+
+    let big = "[[123456789012345678901234567890],[]]";
+    let json: Value = serde_json::from_str(big).unwrap();
+    assert_eq!(big, Packet::try_from(&json).unwrap().to_string());
+    assert_eq!(big, Value::from(&p(big)).to_string());
+
+    for bad in ["[1,", "[-1]", "[1] ", "7"] {
+        assert_eq!(Err(NotAPacket(bad.to_owned())), bad.parse::<Packet>());
+    }
+    for bad in ["[-1]", "[1.5]", "{\"a\":1}", "[null]"] {
+        let json: Value = serde_json::from_str(bad).unwrap();
+        assert!(Packet::try_from(&json).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn day13_explain() {
+    let p = |s: &str| s.parse::<Packet>().unwrap();
+    let (left, right) = (p("[[1],[2,3,4]]"), p("[[1],4]"));
+    assert_eq!(
+        "\
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+",
+        explain(&left, &right).to_string()
+    );
+    let (left, right) = (p("[[4,4],4,4]"), p("[[4,4],4,4,4]"));
+    assert!(explain(&left, &right)
+        .to_string()
+        .ends_with("\n  - Left side ran out of items, so inputs are in the right order\n"));
+
+    let wrong = misordered(include_str!("../assets/day13.test.txt"));
+    assert_eq!(
+        vec![3, 5, 7, 8],
+        wrong.iter().map(|(i, _)| *i).collect_vec()
+    );
+    assert_eq!(
+        "\
+- Compare [9] vs [[8,7,6]]
+  - Compare 9 vs [8,7,6]
+    - Mixed types; convert left to [9] and retry comparison
+    - Compare [9] vs [8,7,6]
+      - Compare 9 vs 8
+        - Right side is smaller, so inputs are not in the right order
+",
+        wrong[0].1
+    );
+    assert_eq!(
+        "\
+- Compare [[[]]] vs [[]]
+  - Compare [[]] vs []
+    - Right side ran out of items, so inputs are not in the right order
+",
+        wrong[2].1
+    );
+
+    let (left, right) = (
+        p("[1,[2,[3,[4,[5,6,7]]]],8,9]"),
+        p("[1,[2,[3,[4,[5,6,0]]]],8,9]"),
+    );
+    let e = explain(&left, &right);
+    assert_eq!(Ordering::Greater, e.order);
+    let last = e.steps.last().unwrap();
+    assert!(matches!(last.event, Event::Smaller(Side::Right)));
+    assert_eq!(6, last.depth);
+}
+
+#[test]
+fn day13_ranks() {
+    let input = include_str!("../assets/day13.test.txt");
+    let all: Vec<Packet> = packets(input).map(Result::unwrap).collect();
+    let p = |s: &str| s.parse::<Packet>().unwrap();
+
+    // Same as sorting everything, probes first among equals.
+    let probes = [
+        "[[2]]",
+        "[[6]]",
+        "[]",
+        "[9]",
+        "[[2]]",
+        "[1,1,3,1,1]",
+        "[[[[[]]]]]",
+        "[2]",
+    ]
+    .map(p);
+    let sorted: Vec<_> = probes
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p, 0, i))
+        .chain(all.iter().map(|p| (p, 1, 0)))
+        .sorted()
+        .collect();
+    let expected: Vec<_> = (0..probes.len())
+        .map(|i| {
+            1 + sorted
+                .iter()
+                .position(|&(_, kind, j)| kind == 0 && j == i)
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(expected, ranks(all.iter().cloned(), &probes));
+    assert_eq!(vec![10, 14], ranks(all.iter().cloned(), &probes[..2]));
+    assert_eq!(vec![1, 2], ranks([], &[p("[1]"), p("[1]")]));
+
+    // The stream is read lazily, so it can stop at the first bad line.
+    let mut stream = packets("[1]\n\n[2]\nnot a packet\n[3]\n");
+    assert_eq!(Some(Ok(p("[1]"))), stream.next());
+    let ranked = ranks(stream.by_ref().map_while(Result::ok), &[p("[5]")]);
+    assert_eq!(vec![2], ranked);
+    assert_eq!(Some(Ok(p("[3]"))), stream.next());
+}
@@ -1,5 +1,3 @@
-use std::{collections::HashSet, iter::repeat};
-
 use itertools::Itertools;
 use nom::{
     bytes::complete::tag,
@@ -11,25 +9,139 @@ use nom::{
 };
 
 type Paths = Vec<Vec<(i16, i16)>>;
-struct Image {
-    data: HashSet<(i16, i16)>,
-    floor: i16,
-}
 
 fn parse(input: &str) -> IResult<&str, Paths> {
     fn number(input: &str) -> IResult<&str, i16> {
-        map_res(digit1, |d| i16::from_str_radix(d, 10))(input)
+        map_res(digit1, |d: &str| d.parse())(input)
     }
     let point = separated_pair(number, char(','), number);
     let path = separated_list1(tag(" -> "), point);
     separated_list1(line_ending, path)(input)
 }
 
+const SOURCE: (i16, i16) = (500, 0);
+
+/// Rock and sand as one bit per cell.
+///
+/// Sand can't end up further from the source sideways than it has fallen,
+/// so the grid covers the rock and the triangle under the source down to
+/// the floor, two below the lowest rock.
+struct Cave {
+    bits: Vec<u64>,
+    x0: i16,
+    width: usize,
+    /// Lowest rock.
+    bottom: i16,
+}
+
+impl Cave {
+    fn new(paths: &Paths) -> Self {
+        let points = || paths.iter().flatten();
+        let bottom = points().map(|p| p.1).max().unwrap_or(0);
+        let floor = bottom + 2;
+        let x0 = points()
+            .map(|p| p.0)
+            .min()
+            .unwrap_or(SOURCE.0)
+            .min(SOURCE.0 - floor);
+        let x1 = points()
+            .map(|p| p.0)
+            .max()
+            .unwrap_or(SOURCE.0)
+            .max(SOURCE.0 + floor);
+        let width = (x1 - x0 + 1) as usize;
+        let mut cave = Cave {
+            bits: vec![0; (width * (floor as usize + 1)).div_ceil(64)],
+            x0,
+            width,
+            bottom,
+        };
+        for path in paths {
+            for (&(x0, y0), &(x1, y1)) in path.iter().tuple_windows() {
+                for (x, y) in (x0.min(x1)..=x0.max(x1)).cartesian_product(y0.min(y1)..=y0.max(y1)) {
+                    cave.set((x, y));
+                }
+            }
+        }
+        cave
+    }
+
+    fn index(&self, (x, y): (i16, i16)) -> usize {
+        (x - self.x0) as usize + y as usize * self.width
+    }
+
+    fn get(&self, p: (i16, i16)) -> bool {
+        let i = self.index(p);
+        self.bits[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    fn set(&mut self, p: (i16, i16)) {
+        let i = self.index(p);
+        self.bits[i / 64] |= 1 << (i % 64);
+    }
+
+    /// Pours sand until it either falls past the lowest rock or, if there's
+    /// a floor, blocks the source. Returns how many grains came to rest.
+    ///
+    /// Each grain follows the one before it until that one settled, so the
+    /// path so far is kept and the next grain starts from one step short of
+    /// where the last one stopped.
+    fn fill(&mut self, floor: bool) -> usize {
+        let mut path = vec![SOURCE];
+        let mut count = 0;
+        while let Some(&(x, y)) = path.last() {
+            if y == self.bottom + 1 {
+                if !floor {
+                    break; // into the abyss
+                }
+            } else if let Some(next) = [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)]
+                .into_iter()
+                .find(|&p| !self.get(p))
+            {
+                path.push(next);
+                continue;
+            }
+            self.set((x, y));
+            count += 1;
+            path.pop();
+        }
+        count
+    }
+}
+
+pub(crate) fn part1(input: &str) -> usize {
+    let (_rest, paths) = parse(input).unwrap();
+    Cave::new(&paths).fill(false)
+}
+
+pub(crate) fn part2(input: &str) -> usize {
+    let (_rest, paths) = parse(input).unwrap();
+    Cave::new(&paths).fill(true)
+}
+
+#[test]
+fn day14() {
+    assert_eq!(24, part1(include_str!("../assets/day14.test.txt")));
+    assert_eq!(93, part2(include_str!("../assets/day14.test.txt")));
+}
+
+//
+// Reference implementation: a set of blocked cells, and every grain starts
+// over from the source.
+//
+
+#[cfg(test)]
+struct Image {
+    data: std::collections::HashSet<(i16, i16)>,
+    floor: i16,
+}
+
+#[cfg(test)]
 fn draw(paths: Paths) -> Image {
     fn sort(a: i16, b: i16) -> (i16, i16) {
         (a.min(b), a.max(b))
     }
-    let mut im = HashSet::new();
+    let mut im = std::collections::HashSet::new();
     let mut floor = 0;
     for path in paths {
         for (&(x0, y0), &(x1, y1)) in path[..path.len() - 1].iter().zip(&path[1..]) {
@@ -44,12 +156,18 @@ fn draw(paths: Paths) -> Image {
     Image { data: im, floor }
 }
 
+#[cfg(test)]
 fn add(a: (i16, i16), b: (i16, i16)) -> (i16, i16) {
     (a.0 + b.0, a.1 + b.1)
 }
 
-pub(crate) fn part1(input: &str) -> usize {
+#[cfg(test)]
+fn part1_reference(input: &str) -> usize {
     fn drop(im: &mut Image) -> bool {
+        // Random caves can fill up to the source.
+        if im.data.contains(&(500, 0)) {
+            return false;
+        }
         let mut pos = (500, 0);
         let moves = [(0, 1), (-1, 1), (1, 1)];
         'step: while pos.1 <= im.floor {
@@ -68,10 +186,11 @@ pub(crate) fn part1(input: &str) -> usize {
 
     let (_rest, paths) = parse(input).unwrap();
     let mut im = draw(paths);
-    repeat(1).take_while(|_| drop(&mut im)).sum()
+    std::iter::repeat(1).take_while(|_| drop(&mut im)).sum()
 }
 
-pub(crate) fn part2(input: &str) -> usize {
+#[cfg(test)]
+fn part2_reference(input: &str) -> usize {
     fn drop(im: &mut Image) -> bool {
         let mut pos = (500, 0);
         let moves = [(0, 1), (-1, 1), (1, 1)];
@@ -91,11 +210,67 @@ pub(crate) fn part2(input: &str) -> usize {
 
     let (_rest, paths) = parse(input).unwrap();
     let mut im = draw(paths);
-    repeat(1).take_while(|_| drop(&mut im)).sum()
+    std::iter::repeat(1).take_while(|_| drop(&mut im)).sum()
+}
+
+/// `npaths` random rock paths, all above `depth`.
+#[cfg(test)]
+fn cave(seed: u64, npaths: usize, depth: i16) -> String {
+    let mut rng = crate::testing::Rng::new(seed);
+    let mut next = move |n: i16| rng.below(n as u64) as i16;
+    (0..npaths)
+        .map(|_| {
+            let spread = depth.min(900);
+            let (mut x, mut y) = (500 - spread / 2 + next(spread), 1 + next(depth - 1));
+            let mut points = vec![(x, y)];
+            for i in 0..1 + next(4) {
+                if i % 2 == 0 {
+                    x = (x - 4 + next(9)).max(0);
+                } else {
+                    y = (y - 4 + next(9)).clamp(1, depth);
+                }
+                points.push((x, y));
+            }
+            points.iter().map(|(x, y)| format!("{x},{y}")).join(" -> ")
+        })
+        .join("\n")
 }
 
 #[test]
-fn day14() {
-    assert_eq!(24, part1(include_str!("../assets/day14.test.txt")));
-    assert_eq!(93, part2(include_str!("../assets/day14.test.txt")));
+fn day14_matches_reference() {
+    for seed in 1..40 {
+        let input = cave(seed, 1 + seed as usize % 12, 5 + seed as i16);
+        assert_eq!(part1_reference(&input), part1(&input), "{input}");
+        assert_eq!(part2_reference(&input), part2(&input), "{input}");
+    }
+    // A lone rock far off to the side.
+    assert_eq!(0, part1("0,0 -> 0,0"));
+    assert_eq!(part2_reference("0,0 -> 0,0"), part2("0,0 -> 0,0"));
+}
+
+// cargo test --release day14_bench -- --ignored --nocapture
+#[test]
+#[ignore]
+fn day14_bench() {
+    use std::time::Instant;
+
+    let input = cave(7, 400, 300);
+
+    let t = Instant::now();
+    let expected = part2_reference(&input);
+    println!("part2_reference: {:?} for {expected} grains", t.elapsed());
+
+    let t = Instant::now();
+    assert_eq!(expected, part2(&input));
+    println!("part2:           {:?}", t.elapsed());
+
+    let t = Instant::now();
+    assert_eq!(part1_reference(&input), part1(&input));
+    println!("both part1s:     {:?}", t.elapsed());
+
+    // Millions of grains.
+    let input = cave(7, 4000, 2000);
+    let t = Instant::now();
+    let grains = part2(&input);
+    println!("part2 deep:      {:?} for {grains} grains", t.elapsed());
 }
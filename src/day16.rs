@@ -18,6 +18,11 @@ use nom::{
 };
 use num_traits::ToPrimitive;
 
+use crate::{
+    graph::all_pairs,
+    plot::{self, Style},
+};
+
 #[derive(Debug)]
 struct Node {
     score: i64,
@@ -34,9 +39,10 @@ struct Graph {
 impl Graph {
     fn edges(&self, node: usize) -> Box<dyn Iterator<Item = usize> + '_> {
         let n = &self.nodes[&node];
-        Box::new((n.edges.0..n.edges.1).map(|e| self.edges[e]).into_iter())
+        Box::new((n.edges.0..n.edges.1).map(|e| self.edges[e]))
     }
 
+    #[cfg_attr(not(feature = "debug"), allow(dead_code))]
     fn to_dot_file(&self) {
         std::fs::write(
             "day16.dot",
@@ -99,7 +105,7 @@ fn parse(input: &str) -> IResult<&str, Doc> {
         id
     }
 
-    let number = map_res(digit1, |d| i64::from_str_radix(d, 10));
+    let number = map_res(digit1, |d: &str| d.parse::<i64>());
     let subject = preceded(
         tag("Valve "),
         map(alpha1, |name| node_id(state.clone(), name)),
@@ -124,8 +130,6 @@ fn parse(input: &str) -> IResult<&str, Doc> {
         }),
     )(input)?;
 
-    dbg!(&state.borrow().names);
-
     Ok((
         rest,
         Doc {
@@ -162,6 +166,7 @@ fn build(doc: Doc) -> Graph {
 struct Mat<T> {
     inner: Vec<T>,
     stride: usize,
+    #[cfg_attr(not(feature = "debug"), allow(dead_code))]
     shape: (usize, usize),
 }
 
@@ -198,57 +203,18 @@ impl<T> IntoIterator for Mat<T> {
     }
 }
 
-impl<T: ToPrimitive + Copy> Mat<T> {
-    fn plot<P: AsRef<std::path::Path>>(&self, filename: P, title: &str) {
-        use plotters::prelude::*;
-        let root = BitMapBackend::new(&filename, (1024, 768)).into_drawing_area();
-
-        root.fill(&WHITE).unwrap();
-
-        let mut chart = ChartBuilder::on(&root)
-            .caption(title, ("sans-serif", 30))
-            .margin(5)
-            .top_x_label_area_size(40)
-            .y_label_area_size(40)
-            .build_cartesian_2d(0..self.shape.0 as i32, self.shape.1 as i32..0)
-            .unwrap();
-
-        chart
-            .configure_mesh()
-            .x_labels(self.shape.1)
-            .y_labels(self.shape.0)
-            .max_light_lines(4)
-            .x_label_offset(35)
-            .y_label_offset(25)
-            .disable_x_mesh()
-            .disable_y_mesh()
-            .label_style(("sans-serif", 20))
-            .draw()
-            .unwrap();
-
-        chart
-            .draw_series(
-                (0..self.shape.0)
-                    .cartesian_product(0..self.shape.1)
-                    .map(|(x, y)| {
-                        let v = self[(y, x)];
-                        let (x, y) = (x as i32, y as i32);
-                        Rectangle::new(
-                            [(x, y), (x + 1, y + 1)],
-                            HSLColor(
-                                240.0 / 360.0 - 240.0 / 360.0 * (v.to_f64().unwrap() / 20.0),
-                                0.7,
-                                0.1 + 0.4 * v.to_f64().unwrap() / 20.0,
-                            )
-                            .filled(),
-                        )
-                    }),
-            )
-            .unwrap();
-
-        // To avoid the IO failure being ignored silently, we manually call the present function
-        root.present().expect("Unable to write result to file, please make sure 'plotters-doc-data' dir exists under current dir");
-        println!("Result has been saved to {:?}", filename.as_ref());
+impl<T: ToPrimitive + Copy> Mat<Option<T>> {
+    /// `None` is drawn at the top of the scale.
+    #[cfg_attr(not(feature = "debug"), allow(dead_code))]
+    fn plot<P: AsRef<std::path::Path>>(&self, filename: P, title: &str) -> anyhow::Result<()> {
+        let (rows, cols) = self.shape;
+        let style = Style {
+            scale: Some(20.0),
+            label_cells: true,
+        };
+        plot::heatmap(filename, title, (cols, rows), style, |x, y| {
+            self[(y, x)].map_or(style.scale.unwrap(), |v| v.to_f64().unwrap())
+        })
     }
 }
 
@@ -258,49 +224,29 @@ pub(crate) fn part1(input: &str) -> i64 {
         // assert_eq!(_rest.len(),0,"rest: {_rest:?}");
         build(doc)
     };
+    #[cfg(feature = "debug")]
     graph.to_dot_file();
 
     let n = graph.nodes.len();
 
     //
     // all pairs shortest paths
-    // next([src,dst]) will be the next node on the shortest path from src to dst
+    // dt([src,dst]) is the time to walk from src to dst, None if it can't
     //
 
-    let (dt, next) = {
-        let mut score = Mat::new(n, n, 1 << 10);
-        let mut next = Mat::new(n, n, n << 2);
-
-        for i in 0..n {
-            score[(i, i)] = 0;
-            next[(i, i)] = i;
-            for j in graph.edges(i) {
-                score[(i, j)] = 1;
-                next[(i, j)] = j;
-            }
-        }
-
-        for k in 0..n {
-            for src in 0..n {
-                for dst in 0..n {
-                    if score[(src, dst)] > score[(src, k)] + score[(k, dst)] {
-                        score[(src, dst)] = score[(src, k)] + score[(k, dst)];
-                        next[(src, dst)] = next[(src, k)];
-                    }
-                }
-            }
+    let dt = {
+        let paths = all_pairs(n, |i| graph.edges(i).map(|j| (j, 1)));
+        let mut dt = Mat::new(n, n, None);
+        for (src, dst) in (0..n).cartesian_product(0..n) {
+            dt[(src, dst)] = paths.dist(src, dst);
         }
         #[cfg(feature = "debug")]
-        {
-            score.plot("dt.png", "Time");
-            next.plot("next.png", "Next");
-        }
-        (score, next)
+        dt.plot("dt.png", "Time").unwrap();
+        dt
     };
 
-    // dfs for best path
-
-    // Given some previous part of the path, find the best continuation
+    // dfs for best path: try each unopened valve as the next one to walk to
+    // and open. Only valves with some flow are worth the trip.
 
     let valves: Vec<_> = graph
         .nodes
@@ -310,59 +256,32 @@ pub(crate) fn part1(input: &str) -> i64 {
         .map(|(k, _)| *k)
         .collect();
 
-    let mut path = vec![graph.start];
-    let mut used = 0u64;
-    let mut flow = 0;
-    let mut total = 0;
-
-    /// compute the sum of the shortest edges from each remaining valve
-    fn lower_bound_time(dt: &Mat<i32>, valves: &[usize], used: u64) -> i32 {
+    fn best(
+        graph: &Graph,
+        dt: &Mat<Option<usize>>,
+        valves: &[usize],
+        at: usize,
+        time: i32,
+        // bit i is set once valves[i] is open
+        used: u64,
+    ) -> i64 {
         valves
             .iter()
             .enumerate()
-            .filter(|(_, &v)| (used >> v) & 1 == 0)
-            .map(|(i, &src)| {
-                // min over unused nodes k in valves s.t. i<j
-                valves
-                    .iter()
-                    .enumerate()
-                    .filter(|(_, &dst)| (used >> dst) & 1 == 0)
-                    .filter(|&(j, _)| i < j)
-                    .map(|(_, &dst)| dt[(src, dst)])
-                    .min()
-                    .unwrap()
+            .filter(|&(i, _)| (used >> i) & 1 == 0)
+            .filter_map(|(i, &v)| {
+                // a minute to open it once there
+                let left = time - dt[(at, v)]? as i32 - 1;
+                (left > 0).then(|| {
+                    graph.nodes[&v].score * left as i64
+                        + best(graph, dt, valves, v, left, used | 1 << i)
+                })
             })
-            .sum()
-    }
-
-    /// assuming valves is ordered in descending order of flow, compute the
-    /// total score as if each was visited in turn and separated by dt
-    /// where dt is the minimum number of steps between the unused valves
-    fn upper_bound_score(graph: Graph, dt: &Mat<i32>, valves: &[usize], used: u64) -> i64 {
-        let unused: Vec<_> = valves.iter().filter(|&v| (used >> v) & 1 == 0).collect();
-        let dt_min = &unused[..]
-            .into_iter()
-            .cartesian_product(&unused[..])
-            .map(|(&src, &dst)| dt[(*src, *dst)])
-            .min()
-            .unwrap();
-        unused
-            .into_iter()
-            .map(|v| graph.nodes[v].score)
-            .fold((0, 0), |(flow, total), f| (flow + f, total + flow))
-            .1
-    }
-
-    let mut best_score=0;
-
-    for choice in 0..valves.len() {
-        let v={
-            
-        };
-        path[choice] = v;
+            .max()
+            .unwrap_or(0)
     }
 
-    todo!()
+    best(&graph, &dt, &valves, graph.start, 30, 0)
 }
 
 #[test]
@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 pub(crate) fn part1(input: &str) -> usize {
     fn inc(counters: u128, c: char) -> u128 {
         counters + (1 << 3 * (c as u8 - b'a'))
@@ -73,6 +75,109 @@ pub(crate) fn part2(input: &str) -> usize {
         + 14
 }
 
+/// Bit for a lowercase letter, or 0 for anything else (like the input's
+/// trailing newline).
+#[cfg_attr(not(test), allow(dead_code))]
+fn letter_bit(c: u8) -> u32 {
+    if c.is_ascii_lowercase() {
+        1 << (c - b'a')
+    } else {
+        0
+    }
+}
+
+/// Position just past the first window of `n` distinct letters, if any.
+///
+/// Windows are checked back to front while accumulating a bitmask of the
+/// letters seen. The first repeat found spoils every window that contains it,
+/// so the search skips straight past it instead of sliding one step. Bytes
+/// that aren't letters spoil windows the same way.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn find_marker(data: &[u8], n: usize) -> Option<usize> {
+    let mut beg = 0;
+    'window: while beg + n <= data.len() {
+        let mut seen = 0u32;
+        for i in (beg..beg + n).rev() {
+            let bit = letter_bit(data[i]);
+            if bit == 0 || seen & bit != 0 {
+                beg = i + 1;
+                continue 'window;
+            }
+            seen ^= bit;
+        }
+        return Some(beg + n);
+    }
+    None
+}
+
+/// Like `find_marker` but keeps a rolling xor of the window's letters.
+///
+/// A letter appearing an even number of times cancels out, so the window is
+/// distinct exactly when all `n` bits are set. Bytes that aren't letters
+/// contribute no bit, so a window holding one never gets there.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn find_marker_rolling(data: &[u8], n: usize) -> Option<usize> {
+    if data.len() < n {
+        return None;
+    }
+    let mut mask = data[..n].iter().fold(0, |m, &c| m ^ letter_bit(c));
+    if mask.count_ones() as usize == n {
+        return Some(n);
+    }
+    for (i, (&old, &cur)) in data.iter().zip(&data[n..]).enumerate() {
+        mask ^= letter_bit(old) ^ letter_bit(cur);
+        if mask.count_ones() as usize == n {
+            return Some(i + n + 1);
+        }
+    }
+    None
+}
+
+/// Splits `data` into `nthreads` chunks and scans them concurrently.
+///
+/// Chunks overlap by `n - 1` bytes so windows straddling a boundary aren't
+/// missed. Threads publish hits to a shared minimum and work through their
+/// chunk a block at a time, giving up once an earlier hit is known.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn find_marker_par(data: &[u8], n: usize, nthreads: usize) -> Option<usize> {
+    // Windows scanned between checks of the shared minimum.
+    const BLOCK: usize = 1 << 14;
+
+    let nthreads = nthreads.max(1);
+    let step = data.len().div_ceil(nthreads);
+    if n == 0 || step < n {
+        return find_marker(data, n);
+    }
+    let best = AtomicUsize::new(usize::MAX);
+    std::thread::scope(|s| {
+        for beg in (0..data.len()).step_by(step) {
+            let best = &best;
+            s.spawn(move || {
+                let end = (beg + step).min(data.len());
+                for b in (beg..end).step_by(BLOCK) {
+                    if best.load(Ordering::Relaxed) <= b {
+                        return;
+                    }
+                    let e = ((b + BLOCK).min(end) + n - 1).min(data.len());
+                    if let Some(i) = find_marker(&data[b..e], n) {
+                        best.fetch_min(b + i, Ordering::Relaxed);
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    Some(best.into_inner()).filter(|&i| i != usize::MAX)
+}
+
+#[cfg(test)]
+fn signal(seed: u64, len: usize, nletters: u8) -> Vec<u8> {
+    let mut rng = crate::testing::Rng::new(seed);
+    (0..len)
+        .map(|_| b'a' + rng.below(nletters as u64) as u8)
+        .collect()
+}
+
 #[test]
 fn day6() {
     assert_eq!(7, part1("mjqjpqmgbljsphdztnvjfqwrcgsmlb"));
@@ -87,3 +192,103 @@ fn day6() {
     assert_eq!(29, part2("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg"));
     assert_eq!(26, part2("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw"));
 }
+
+#[test]
+fn day6_scanners_agree() {
+    use itertools::Itertools;
+
+    fn reference(data: &[u8], n: usize) -> Option<usize> {
+        data.windows(n)
+            .position(|w| w.iter().all_unique())
+            .map(|i| i + n)
+    }
+
+    for seed in 1..2000 {
+        let nletters = 4 + (seed % 23) as u8;
+        let data = signal(seed, 1 + (seed as usize * 7) % 300, nletters);
+        let input = std::str::from_utf8(&data).unwrap();
+        for n in [1, 2, 4, 14] {
+            let expected = reference(&data, n);
+            assert_eq!(expected, find_marker(&data, n), "seed:{seed} n:{n}");
+            assert_eq!(expected, find_marker_rolling(&data, n), "seed:{seed} n:{n}");
+            for nthreads in [1, 3, 8] {
+                assert_eq!(
+                    expected,
+                    find_marker_par(&data, n, nthreads),
+                    "seed:{seed} n:{n} nthreads:{nthreads}"
+                );
+            }
+            // part1 and part2 only give meaningful answers when there is a
+            // marker to find.
+            match (n, expected) {
+                (4, Some(i)) => assert_eq!(i, part1(input), "seed:{seed}"),
+                (14, Some(i)) => assert_eq!(i, part2(input), "seed:{seed}"),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn day6_trailing_newline() {
+    // The puzzle input is one line of letters ending in a newline.
+    let input = b"abcdaaaaaaaaaaaa\n";
+    assert_eq!(Some(4), find_marker(input, 4));
+    assert_eq!(Some(4), find_marker_rolling(input, 4));
+    assert_eq!(Some(4), find_marker_par(input, 4, 4));
+
+    // A newline never counts as a distinct letter.
+    assert_eq!(None, find_marker(b"abc\n", 4));
+    assert_eq!(None, find_marker_rolling(b"abc\n", 4));
+    assert_eq!(None, find_marker_par(b"abc\nabc\n", 4, 2));
+
+    for seed in 1..20 {
+        // Few enough letters that most markers come from the planted run,
+        // which lands somewhere past the first few blocks.
+        let mut data = signal(seed, 100_000, 3 + (seed % 4) as u8);
+        let at = (seed as usize * 1999) % 90_000;
+        data[at..at + 14].copy_from_slice(b"abcdefghijklmn");
+        data.push(b'\n');
+        assert_eq!(Some(at + 14), find_marker(&data, 14), "seed:{seed}");
+        for n in [4, 14] {
+            let expected = find_marker(&data, n);
+            assert_eq!(expected, find_marker_rolling(&data, n), "seed:{seed} n:{n}");
+            for nthreads in [2, 7] {
+                assert_eq!(
+                    expected,
+                    find_marker_par(&data, n, nthreads),
+                    "seed:{seed} n:{n} nthreads:{nthreads}"
+                );
+            }
+        }
+    }
+}
+
+// cargo test --release day6_bench -- --ignored --nocapture
+#[test]
+#[ignore]
+fn day6_bench() {
+    use std::time::Instant;
+
+    // A signal that only contains a marker at the very end.
+    let mut data = signal(42, 1 << 26, 13);
+    data.extend(b"abcdefghijklmnopqrstuvwxyz");
+    let input = std::str::from_utf8(&data).unwrap();
+    let expected = data.len() - 12;
+
+    let t = Instant::now();
+    assert_eq!(expected, part2(input));
+    println!("part2:               {:?}", t.elapsed());
+
+    let t = Instant::now();
+    assert_eq!(Some(expected), find_marker_rolling(&data, 14));
+    println!("find_marker_rolling: {:?}", t.elapsed());
+
+    let t = Instant::now();
+    assert_eq!(Some(expected), find_marker(&data, 14));
+    println!("find_marker:         {:?}", t.elapsed());
+
+    let t = Instant::now();
+    assert_eq!(Some(expected), find_marker_par(&data, 14, 8));
+    println!("find_marker_par:     {:?}", t.elapsed());
+}
@@ -1,94 +1,608 @@
-use std::collections::HashMap;
+use std::fmt;
 
+use itertools::Itertools;
 use nom::{
     branch::alt,
-    bytes::complete::tag,
-    character::complete::{alpha1, digit1, line_ending, not_line_ending},
-    combinator::{map, map_res, opt, value},
-    multi::{fold_many1, many1},
-    sequence::{preceded, terminated, tuple},
+    bytes::complete::{tag, take_till1},
+    character::complete::{digit1, line_ending, space1},
+    combinator::{eof, map, map_res},
+    multi::{many0, many1},
+    sequence::{pair, preceded, separated_pair, terminated},
     IResult,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum Command<'a> {
     Root,
     Pop,
     Push(&'a str),
-    Size(usize),
+    /// Entries along with how many lines below the `$ ls` they are.
+    List(Vec<(usize, Entry<'a>)>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Entry<'a> {
+    Dir(&'a str),
+    File(&'a str, usize),
+}
+
+/// Something odd in the log that could still be made sense of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Warning {
+    /// `cd` into a directory that no `ls` had shown. It's created empty.
+    UnlistedDir { line: usize, path: String },
+    /// `cd ..` while at the root. It's ignored.
+    PopAtRoot { line: usize },
+}
+
+/// A log that doesn't describe any consistent file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Error {
+    Parse {
+        line: usize,
+    },
+    /// `cd` into something that was listed as a file.
+    NotADirectory {
+        line: usize,
+        path: String,
+    },
+    /// The same name was listed as both a file and a directory.
+    KindConflict {
+        line: usize,
+        path: String,
+    },
+    /// The same file was listed twice with different sizes.
+    ConflictingSize {
+        line: usize,
+        path: String,
+        old: usize,
+        new: usize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { line } => write!(f, "line {line}: could not parse"),
+            Error::NotADirectory { line, path } => {
+                write!(f, "line {line}: {path} is not a directory")
+            }
+            Error::KindConflict { line, path } => {
+                write!(
+                    f,
+                    "line {line}: {path} listed as both a file and a directory"
+                )
+            }
+            Error::ConflictingSize {
+                line,
+                path,
+                old,
+                new,
+            } => write!(
+                f,
+                "line {line}: {path} listed with size {new} but was {old}"
+            ),
+        }
+    }
 }
 
-fn parse(input: &str) -> IResult<&str, Vec<Command>> {
-    fn ls(input: &str) -> IResult<&str, usize> {
-        preceded(
-            terminated(tag("$ ls"), line_ending),
-            fold_many1(
-                terminated(
+impl std::error::Error for Error {}
+
+/// Returns each command along with the (1-based) line it's on.
+fn parse(input: &str) -> Result<Vec<(usize, Command<'_>)>, Error> {
+    // Names are anything up to the next whitespace.
+    fn name(input: &str) -> IResult<&str, &str> {
+        take_till1(char::is_whitespace)(input)
+    }
+
+    // Returns how many line endings were consumed, blank lines included.
+    fn end(input: &str) -> IResult<&str, usize> {
+        alt((map(eof, |_| 0), map(many1(line_ending), |ends| ends.len())))(input)
+    }
+
+    fn ls(input: &str) -> IResult<&str, Vec<(usize, Entry<'_>)>> {
+        map(
+            preceded(
+                terminated(tag("$ ls"), line_ending),
+                many0(pair(
                     alt((
-                        value(0, tuple((tag("dir "), alpha1))),
-                        map_res(terminated(digit1, not_line_ending), |sz| {
-                            usize::from_str_radix(sz, 10)
-                        }),
+                        map(preceded(tag("dir "), name), Entry::Dir),
+                        map(
+                            separated_pair(map_res(digit1, |sz: &str| sz.parse()), space1, name),
+                            |(sz, name)| Entry::File(name, sz),
+                        ),
                     )),
-                    opt(line_ending),
-                ),
-                || 0,
-                |acc, sz| acc + sz,
+                    end,
+                )),
             ),
+            |entries| {
+                entries
+                    .into_iter()
+                    .scan(1, |offset, (e, ends)| {
+                        let here = *offset;
+                        *offset += ends;
+                        Some((here, e))
+                    })
+                    .collect()
+            },
         )(input)
     }
 
-    many1(terminated(
+    fn command(input: &str) -> IResult<&str, Command<'_>> {
         alt((
-            value(Command::Root, tag("$ cd /")),
-            value(Command::Pop, tag("$ cd ..")),
-            map(preceded(tag("$ cd "), alpha1), |dir| Command::Push(dir)),
-            map(ls, |sz| Command::Size(sz)),
-        )),
-        opt(line_ending),
-    ))(input)
-}
-
-fn build_dirs(commands: Vec<Command>) -> HashMap<String, usize> {
-    let mut dirs: HashMap<String, usize> = HashMap::new();
-    let mut cwd = Vec::new();
-    for cmd in commands {
-        match cmd {
-            Command::Root => cwd.clear(),
-            Command::Pop => {
-                cwd.pop();
+            map(
+                terminated(preceded(tag("$ cd "), name), end),
+                |dir| match dir {
+                    "/" => Command::Root,
+                    ".." => Command::Pop,
+                    _ => Command::Push(dir),
+                },
+            ),
+            map(ls, Command::List),
+        ))(input)
+    }
+
+    let mut commands = Vec::new();
+    let (mut rest, _) = many0(line_ending::<_, ()>)(input).unwrap();
+    let mut line = 1 + input[..input.len() - rest.len()].matches('\n').count();
+    while !rest.is_empty() {
+        let (next, cmd) = command(rest).map_err(|_| Error::Parse { line })?;
+        commands.push((line, cmd));
+        line += rest[..rest.len() - next.len()].matches('\n').count();
+        rest = next;
+    }
+    Ok(commands)
+}
+
+#[derive(Debug)]
+enum Kind {
+    Dir(Vec<usize>),
+    File(usize),
+}
+
+#[derive(Debug)]
+struct Node<'a> {
+    name: &'a str,
+    parent: usize,
+    kind: Kind,
+}
+
+/// Directory tree reconstructed from a terminal log.
+///
+/// Nodes live in an arena and are addressed by index. The root is node 0 and
+/// is its own parent. A directory's children are kept sorted by name.
+#[derive(Debug)]
+pub(crate) struct Fs<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Entry<'a> {
+    fn name(&self) -> &'a str {
+        match *self {
+            Entry::Dir(name) => name,
+            Entry::File(name, _) => name,
+        }
+    }
+}
+
+impl<'a> Fs<'a> {
+    pub(crate) const ROOT: usize = 0;
+
+    fn new() -> Self {
+        Fs {
+            nodes: vec![Node {
+                name: "/",
+                parent: Self::ROOT,
+                kind: Kind::Dir(Vec::new()),
+            }],
+        }
+    }
+
+    /// Rebuilds the tree from a log, along with anything suspicious found
+    /// along the way.
+    ///
+    /// Listing the same directory more than once is fine as long as the
+    /// listings agree.
+    pub(crate) fn from_log(input: &'a str) -> Result<(Self, Vec<Warning>), Error> {
+        let mut fs = Fs::new();
+        let mut warnings = Vec::new();
+        let mut cwd = Self::ROOT;
+        for (line, cmd) in parse(input)? {
+            match cmd {
+                Command::Root => cwd = Self::ROOT,
+                Command::Pop => match fs.parent(cwd) {
+                    Some(parent) => cwd = parent,
+                    None => warnings.push(Warning::PopAtRoot { line }),
+                },
+                Command::Push(name) => {
+                    cwd = match fs.child(cwd, name) {
+                        Some(id) if fs.is_dir(id) => id,
+                        Some(id) => {
+                            return Err(Error::NotADirectory {
+                                line,
+                                path: fs.path(id),
+                            })
+                        }
+                        None => {
+                            let id = fs.insert(cwd, name, Kind::Dir(Vec::new()));
+                            warnings.push(Warning::UnlistedDir {
+                                line,
+                                path: fs.path(id),
+                            });
+                            id
+                        }
+                    }
+                }
+                Command::List(entries) => {
+                    for (offset, e) in entries {
+                        let line = line + offset;
+                        match (e, fs.child(cwd, e.name())) {
+                            (Entry::Dir(name), None) => {
+                                fs.insert(cwd, name, Kind::Dir(Vec::new()));
+                            }
+                            (Entry::File(name, sz), None) => {
+                                fs.insert(cwd, name, Kind::File(sz));
+                            }
+                            (Entry::Dir(_), Some(id)) if fs.is_dir(id) => {}
+                            (Entry::File(_, sz), Some(id)) if !fs.is_dir(id) => {
+                                if fs.size(id) != sz {
+                                    return Err(Error::ConflictingSize {
+                                        line,
+                                        path: fs.path(id),
+                                        old: fs.size(id),
+                                        new: sz,
+                                    });
+                                }
+                            }
+                            (_, Some(id)) => {
+                                return Err(Error::KindConflict {
+                                    line,
+                                    path: fs.path(id),
+                                })
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok((fs, warnings))
+    }
+
+    /// The child of directory `dir` called `name`, if there is one.
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        let children = self.children(dir);
+        children
+            .binary_search_by(|&c| self.name(c).cmp(name))
+            .ok()
+            .map(|i| children[i])
+    }
+
+    /// Adds a new node called `name` to the directory `parent`. Returns the
+    /// id of the new node.
+    fn insert(&mut self, parent: usize, name: &'a str, kind: Kind) -> usize {
+        let id = self.nodes.len();
+        let i = self
+            .children(parent)
+            .partition_point(|&c| self.name(c) < name);
+        self.nodes.push(Node { name, parent, kind });
+        if let Kind::Dir(children) = &mut self.nodes[parent].kind {
+            children.insert(i, id);
+        }
+        id
+    }
+
+    pub(crate) fn name(&self, id: usize) -> &'a str {
+        self.nodes[id].name
+    }
+
+    pub(crate) fn parent(&self, id: usize) -> Option<usize> {
+        (id != Self::ROOT).then(|| self.nodes[id].parent)
+    }
+
+    pub(crate) fn is_dir(&self, id: usize) -> bool {
+        matches!(self.nodes[id].kind, Kind::Dir(_))
+    }
+
+    pub(crate) fn children(&self, id: usize) -> &[usize] {
+        match &self.nodes[id].kind {
+            Kind::Dir(children) => children,
+            Kind::File(_) => &[],
+        }
+    }
+
+    /// Absolute path of a node, e.g. `/a/e`.
+    pub(crate) fn path(&self, id: usize) -> String {
+        let mut names = Vec::new();
+        let mut cur = id;
+        while let Some(parent) = self.parent(cur) {
+            names.push(self.name(cur));
+            cur = parent;
+        }
+        "/".to_owned() + &names.into_iter().rev().join("/")
+    }
+
+    /// Finds a node from a `/`-separated path. Paths are relative to the
+    /// root whether or not they start with `/`.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn lookup(&self, path: &str) -> Option<usize> {
+        path.split('/')
+            .filter(|s| !s.is_empty())
+            .try_fold(Self::ROOT, |cur, name| self.child(cur, name))
+    }
+
+    /// Size of a file, or the total size of everything under a directory.
+    pub(crate) fn size(&self, id: usize) -> usize {
+        match &self.nodes[id].kind {
+            Kind::Dir(children) => children.iter().map(|&c| self.size(c)).sum(),
+            &Kind::File(sz) => sz,
+        }
+    }
+
+    /// Depth-first, pre-order walk over every node starting at the root.
+    pub(crate) fn walk(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut stack = vec![Self::ROOT];
+        std::iter::from_fn(move || {
+            let cur = stack.pop()?;
+            stack.extend(self.children(cur).iter().rev());
+            Some(cur)
+        })
+    }
+
+    pub(crate) fn dirs(&self) -> impl Iterator<Item = usize> + '_ {
+        self.walk().filter(|&id| self.is_dir(id))
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn files(&self) -> impl Iterator<Item = usize> + '_ {
+        self.walk().filter(|&id| !self.is_dir(id))
+    }
+
+    /// The `n` biggest files, largest first. Ties are ordered by path.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn largest_files(&self, n: usize) -> Vec<usize> {
+        self.files()
+            .sorted_by(|&a, &b| {
+                self.size(b)
+                    .cmp(&self.size(a))
+                    .then_with(|| self.path(a).cmp(&self.path(b)))
+            })
+            .take(n)
+            .collect()
+    }
+
+    /// Directories whose absolute path matches `pattern`. See `glob`.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn find_dirs(&self, pattern: &str) -> Vec<usize> {
+        self.dirs()
+            .filter(|&id| glob(pattern.as_bytes(), self.path(id).as_bytes()))
+            .collect()
+    }
+}
+
+/// A directory that could be deleted to make room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub(crate) path: String,
+    pub(crate) size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Plan {
+    /// How much space has to be freed.
+    pub(crate) needed: usize,
+    /// Every directory that frees enough space on its own, smallest first.
+    pub(crate) singles: Vec<Candidate>,
+    /// The non-nested directories that together free enough space while
+    /// deleting as little as possible. Fewer directories win ties.
+    pub(crate) multi: Vec<Candidate>,
+}
+
+impl Fs<'_> {
+    fn candidate(&self, id: usize) -> Candidate {
+        Candidate {
+            path: self.path(id),
+            size: self.size(id),
+        }
+    }
+
+    /// Works out what to delete so that a disk of size `disk` ends up with at
+    /// least `required` free. Returns `None` when that's impossible.
+    pub(crate) fn plan(&self, disk: usize, required: usize) -> Option<Plan> {
+        if required > disk {
+            return None;
+        }
+        let needed = (self.size(Self::ROOT) + required).saturating_sub(disk);
+
+        let singles = self
+            .dirs()
+            .map(|id| self.candidate(id))
+            .filter(|c| c.size >= needed)
+            .sorted_by(|a, b| a.size.cmp(&b.size).then_with(|| a.path.cmp(&b.path)))
+            .collect();
+
+        // Directories in pre-order, so each one's descendants are the run
+        // right after it. `skip[i]` is the index just past that run.
+        let dirs: Vec<_> = self.dirs().collect();
+        let sizes: Vec<_> = dirs.iter().map(|&id| self.size(id)).collect();
+        let mut skip = vec![dirs.len(); dirs.len()];
+        let mut open = Vec::new();
+        for (i, &id) in dirs.iter().enumerate() {
+            while let Some(&j) = open.last() {
+                if self.parent(id) == Some(dirs[j]) {
+                    break;
+                }
+                skip[j] = i;
+                open.pop();
             }
-            Command::Push(d) => {
-                cwd.push(d);
+            open.push(i);
+        }
+        // `reach[i]` is the most that can be freed using dirs[i..]. Taking a
+        // directory always frees at least as much as any of its descendants.
+        let mut reach = vec![0; dirs.len() + 1];
+        for i in (0..dirs.len()).rev() {
+            reach[i] = sizes[i] + reach[skip[i]];
+        }
+
+        // Branch and bound over the non-nested subsets: either take dirs[i]
+        // and jump past its descendants, or leave it and move on.
+        struct Search<'s> {
+            sizes: &'s [usize],
+            skip: &'s [usize],
+            reach: &'s [usize],
+            needed: usize,
+            taken: Vec<usize>,
+            best: Option<(usize, Vec<usize>)>,
+        }
+
+        impl Search<'_> {
+            fn visit(&mut self, i: usize, total: usize) {
+                if total >= self.needed {
+                    let better = match &self.best {
+                        Some((best, taken)) => (total, self.taken.len()) < (*best, taken.len()),
+                        None => true,
+                    };
+                    if better {
+                        self.best = Some((total, self.taken.clone()));
+                    }
+                    return;
+                }
+                if i == self.sizes.len() || total + self.reach[i] < self.needed {
+                    return;
+                }
+                let fits = match &self.best {
+                    Some((best, _)) => total + self.sizes[i] <= *best,
+                    None => true,
+                };
+                if fits {
+                    self.taken.push(i);
+                    self.visit(self.skip[i], total + self.sizes[i]);
+                    self.taken.pop();
+                }
+                self.visit(i + 1, total);
+            }
+        }
+
+        let mut search = Search {
+            sizes: &sizes,
+            skip: &skip,
+            reach: &reach,
+            needed,
+            taken: Vec::new(),
+            best: None,
+        };
+        search.visit(0, 0);
+        let multi = search
+            .best
+            .map(|(_, taken)| taken.into_iter().map(|i| self.candidate(dirs[i])).collect())
+            .unwrap_or_default();
+
+        Some(Plan {
+            needed,
+            singles,
+            multi,
+        })
+    }
+}
+
+impl Fs<'_> {
+    /// Listing in the style of `du -b`: one `size<TAB>path` line per
+    /// directory, children before parents, siblings sorted by name. With
+    /// `all`, files are listed too, like `du -ab`.
+    ///
+    /// Directories count only what's in them, not their own entry size.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn du(&self, all: bool) -> String {
+        fn visit(fs: &Fs, out: &mut String, id: usize, all: bool) -> usize {
+            let size = match fs.nodes[id].kind {
+                Kind::Dir(ref children) => children.iter().map(|&c| visit(fs, out, c, all)).sum(),
+                Kind::File(sz) => sz,
+            };
+            if all || fs.is_dir(id) {
+                *out += &format!("{size}\t{}\n", fs.path(id));
             }
-            Command::Size(sz) => {
-                let mut path = String::new();
-                *dirs.entry("/".to_string()).or_default() += sz;
-                for d in cwd.iter() {
-                    path += d;
-                    path += "/";
-                    *dirs.entry(path.clone()).or_default() += sz;
+            size
+        }
+        let mut out = String::new();
+        visit(self, &mut out, Self::ROOT, all);
+        out
+    }
+
+    /// The whole tree as a single line of JSON. Every node has a `name` and
+    /// `size`. Directories also have their `children`, sorted by name.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn to_json(&self) -> String {
+        fn quote(s: &str) -> String {
+            let mut out = String::from('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out += "\\\"",
+                    '\\' => out += "\\\\",
+                    c if (c as u32) < 0x20 => out += &format!("\\u{:04x}", c as u32),
+                    c => out.push(c),
                 }
             }
+            out + "\""
         }
+        fn visit(fs: &Fs, id: usize) -> String {
+            let head = format!("{{\"name\":{},\"size\":{}", quote(fs.name(id)), fs.size(id));
+            if fs.is_dir(id) {
+                let children = fs.children(id).iter().map(|&c| visit(fs, c)).join(",");
+                format!("{head},\"children\":[{children}]}}")
+            } else {
+                head + "}"
+            }
+        }
+        visit(self, Self::ROOT)
+    }
+}
+
+/// Matches `text` against a shell-style pattern: `?` matches any single
+/// character except `/`, `*` matches any run without a `/` and `**` matches
+/// anything.
+#[cfg_attr(not(test), allow(dead_code))]
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob(rest, &text[i..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob(rest, &text[i..])),
+        [b'?', rest @ ..] => matches!(text, [c, ..] if *c != b'/') && glob(rest, &text[1..]),
+        [p, rest @ ..] => text.first() == Some(p) && glob(rest, &text[1..]),
+    }
+}
+
+/// Prints the tree in the same style as the puzzle text.
+impl fmt::Display for Fs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn visit(fs: &Fs, f: &mut fmt::Formatter<'_>, id: usize, depth: usize) -> fmt::Result {
+            let indent = 2 * depth;
+            match fs.nodes[id].kind {
+                Kind::Dir(_) => writeln!(f, "{:indent$}- {} (dir)", "", fs.name(id))?,
+                Kind::File(sz) => writeln!(f, "{:indent$}- {} (file, size={sz})", "", fs.name(id))?,
+            }
+            for &c in fs.children(id) {
+                visit(fs, f, c, depth + 1)?;
+            }
+            Ok(())
+        }
+        visit(self, f, Self::ROOT, 0)
     }
-    dirs
 }
 
 pub(crate) fn part1(input: &str) -> usize {
-    let (_rest, commands) = parse(input).unwrap();
-    let dirs = build_dirs(commands);
-    dirs.values().filter(|&sz| *sz <= 100000).sum()
+    let (fs, _warnings) = Fs::from_log(input).unwrap();
+    fs.dirs()
+        .map(|id| fs.size(id))
+        .filter(|&sz| sz <= 100000)
+        .sum()
 }
 
 pub(crate) fn part2(input: &str) -> usize {
-    let (_rest, commands) = parse(input).unwrap();
-    let dirs = build_dirs(commands);
-
-    let remaining = 70000000 - dirs.get("/").unwrap();
-    let thresh = 30000000 - remaining;
+    let (fs, _warnings) = Fs::from_log(input).unwrap();
 
-    *dirs.values().filter(|&sz| *sz > thresh).min().unwrap()
+    let plan = fs.plan(70000000, 30000000).unwrap();
+    plan.singles[0].size
 }
 
 #[test]
@@ -96,3 +610,179 @@ fn day7() {
     assert_eq!(95437, part1(include_str!("../assets/day7.test.txt")));
     assert_eq!(24933642, part2(include_str!("../assets/day7.test.txt")));
 }
+
+#[test]
+fn day7_tree() {
+    let (fs, warnings) = Fs::from_log(include_str!("../assets/day7.test.txt")).unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(
+        "\
+- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+",
+        fs.to_string()
+    );
+
+    let e = fs.lookup("/a/e").unwrap();
+    assert_eq!(584, fs.size(e));
+    assert_eq!("/a/e", fs.path(e));
+    assert_eq!(Some(e), fs.lookup("a/e/"));
+    assert_eq!(None, fs.lookup("/a/x"));
+    assert_eq!(48381165, fs.size(Fs::ROOT));
+
+    let largest: Vec<_> = fs
+        .largest_files(2)
+        .into_iter()
+        .map(|id| fs.path(id))
+        .collect();
+    assert_eq!(vec!["/b.txt", "/c.dat"], largest);
+
+    let dirs = |pattern| -> Vec<_> {
+        fs.find_dirs(pattern)
+            .into_iter()
+            .map(|id| fs.path(id))
+            .collect()
+    };
+    assert_eq!(vec!["/a", "/d"], dirs("/?"));
+    assert_eq!(vec!["/", "/a", "/a/e", "/d"], dirs("/**"));
+    assert_eq!(vec!["/a/e"], dirs("/*/e"));
+}
+
+#[test]
+fn day7_odd_logs() {
+    // Odd names and a repeated listing
+    let (fs, warnings) = Fs::from_log(
+        "$ cd /\n$ ls\ndir a.b\n10 x-1\n$ cd a.b\n$ ls\n5 y_2.tar.gz\n$ cd ..\n$ ls\ndir a.b\n10 x-1\n",
+    )
+    .unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(15, fs.size(Fs::ROOT));
+    assert_eq!(5, fs.size(fs.lookup("/a.b/y_2.tar.gz").unwrap()));
+
+    // Same sized files come out by path, not in walk order.
+    let (fs, _) = Fs::from_log("$ ls\ndir a\n5 a-b\n$ cd a\n$ ls\n5 x\n").unwrap();
+    let largest: Vec<_> = fs
+        .largest_files(2)
+        .into_iter()
+        .map(|id| fs.path(id))
+        .collect();
+    assert_eq!(vec!["/a-b", "/a/x"], largest);
+
+    let (fs, warnings) = Fs::from_log("$ cd ..\n$ cd d-1\n$ ls\n7 f\n").unwrap();
+    assert_eq!(
+        vec![
+            Warning::PopAtRoot { line: 1 },
+            Warning::UnlistedDir {
+                line: 2,
+                path: "/d-1".to_owned()
+            }
+        ],
+        warnings
+    );
+    assert_eq!(7, fs.size(Fs::ROOT));
+
+    assert_eq!(
+        Err(Error::ConflictingSize {
+            line: 5,
+            path: "/f".to_owned(),
+            old: 7,
+            new: 8
+        }),
+        Fs::from_log("$ ls\n7 f\n\n$ ls\n8 f\n").map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::KindConflict {
+            line: 4,
+            path: "/f".to_owned()
+        }),
+        Fs::from_log("$ ls\n7 f\n$ ls\ndir f\n").map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::NotADirectory {
+            line: 3,
+            path: "/f".to_owned()
+        }),
+        Fs::from_log("$ ls\n7 f\n$ cd f\n").map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::Parse { line: 3 }),
+        Fs::from_log("$ ls\n7 f\n$ rm f\n").map(|_| ())
+    );
+
+    // Blank lines inside a listing still count.
+    assert_eq!(
+        Err(Error::ConflictingSize {
+            line: 5,
+            path: "/b".to_owned(),
+            old: 2,
+            new: 3
+        }),
+        Fs::from_log("$ ls\n1 a\n\n2 b\n3 b\n").map(|_| ())
+    );
+    assert_eq!(
+        Err(Error::KindConflict {
+            line: 6,
+            path: "/a".to_owned()
+        }),
+        Fs::from_log("$ ls\n1 a\n\n\n\ndir a").map(|_| ())
+    );
+}
+
+#[test]
+fn day7_plan() {
+    let (fs, _) = Fs::from_log(include_str!("../assets/day7.test.txt")).unwrap();
+    let plan = fs.plan(70000000, 30000000).unwrap();
+    assert_eq!(8381165, plan.needed);
+    let paths = |cs: &[Candidate]| -> Vec<_> { cs.iter().map(|c| c.path.clone()).collect() };
+    assert_eq!(vec!["/d", "/"], paths(&plan.singles));
+    assert_eq!(vec!["/d"], paths(&plan.multi));
+
+    // Nothing to do when there's already enough room.
+    let plan = fs.plan(100000000, 30000000).unwrap();
+    assert_eq!(0, plan.needed);
+    assert!(plan.multi.is_empty());
+
+    assert_eq!(None, fs.plan(10, 30));
+
+    // Two small directories beat the only big enough one.
+    let (fs, _) = Fs::from_log(
+        "$ cd /\n$ ls\ndir x\ndir y\ndir z\n$ cd x\n$ ls\n60 a\ndir w\n$ cd w\n$ ls\n1 b\n\
+         $ cd /\n$ cd y\n$ ls\n50 a\n$ cd ..\n$ cd z\n$ ls\n45 a\n",
+    )
+    .unwrap();
+    let plan = fs.plan(201, 135).unwrap();
+    assert_eq!(90, plan.needed);
+    assert_eq!(vec!["/"], paths(&plan.singles));
+    assert_eq!(vec!["/y", "/z"], paths(&plan.multi));
+    assert_eq!(95, plan.multi.iter().map(|c| c.size).sum::<usize>());
+}
+
+#[test]
+fn day7_export() {
+    let (fs, _) = Fs::from_log(include_str!("../assets/day7.test.txt")).unwrap();
+    assert_eq!(
+        "584\t/a/e\n94853\t/a\n24933642\t/d\n48381165\t/\n",
+        fs.du(false)
+    );
+    assert!(fs
+        .du(true)
+        .starts_with("584\t/a/e/i\n584\t/a/e\n29116\t/a/f\n"));
+
+    let (fs, _) = Fs::from_log("$ ls\ndir a\n1 \"q\\\n$ cd a\n$ ls\n2 b\n").unwrap();
+    assert_eq!(
+        r#"{"name":"/","size":3,"children":[{"name":"\"q\\","size":1},{"name":"a","size":2,"children":[{"name":"b","size":2}]}]}"#,
+        fs.to_json()
+    );
+}
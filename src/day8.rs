@@ -1,4 +1,4 @@
-use std::{cmp::Reverse, collections::BinaryHeap, ops::Index};
+use std::{ops::Index, path::Path};
 
 use itertools::Itertools;
 use nom::{
@@ -8,6 +8,9 @@ use nom::{
     sequence::terminated,
     IResult,
 };
+use num_traits::ToPrimitive;
+
+use crate::plot::{self, Style};
 
 struct Image<'a> {
     data: &'a [u8],
@@ -23,7 +26,7 @@ impl<'a> Index<(usize, usize)> for Image<'a> {
     }
 }
 
-fn parse(input: &str) -> IResult<&str, Image> {
+fn parse(input: &str) -> IResult<&str, Image<'_>> {
     let (rest, width) = terminated(map(digit1, |d: &str| d.len()), opt(line_ending))(input)?;
     let (rest, height) = many0_count(terminated(digit1, opt(line_ending)))(rest)?;
     Ok((
@@ -61,7 +64,41 @@ where
     out
 }
 
-pub(crate) fn part1(input: &str) -> usize {
+/// Per-tree values, row by row.
+pub(crate) struct Grid<T> {
+    pub(crate) data: Vec<T>,
+    pub(crate) shape: (usize, usize),
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &Self::Output {
+        &self.data[x + y * self.shape.0]
+    }
+}
+
+impl<T> Grid<T> {
+    fn from_image(im: &Image, f: impl Fn(usize) -> T) -> Self {
+        Grid {
+            data: (0..im.shape.1)
+                .cartesian_product(0..im.shape.0)
+                .map(|(y, x)| f(x + y * im.stride))
+                .collect(),
+            shape: im.shape,
+        }
+    }
+}
+
+// Sides of the grid a tree can be seen from. See `visibility`.
+pub(crate) const TOP: u8 = 1;
+pub(crate) const LEFT: u8 = 2;
+pub(crate) const BOTTOM: u8 = 4;
+pub(crate) const RIGHT: u8 = 8;
+
+/// For each tree, the sides of the grid it can be seen from as a combination
+/// of `TOP`, `LEFT`, `BOTTOM` and `RIGHT`. Zero means it's hidden.
+pub(crate) fn visibility(input: &str) -> Grid<u8> {
     let (_rest, im) = parse(input).unwrap();
 
     let top = scan(&im, (0, -1), 0..im.shape.0, 1..im.shape.1);
@@ -72,212 +109,388 @@ pub(crate) fn part1(input: &str) -> usize {
     // if a cell has the highest tree then it's index will appear in one of
     // the scans at a corresponding cell
 
-    (0..im.shape.0)
-        .cartesian_product(0..im.shape.1)
-        .map(|(x, y)| x + y * im.stride)
-        .filter(|&i| top[i] == i || left[i] == i || bot[i] == i || right[i] == i)
+    Grid::from_image(&im, |i| {
+        [(&top, TOP), (&left, LEFT), (&bot, BOTTOM), (&right, RIGHT)]
+            .iter()
+            .filter(|(scan, _)| scan[i] == i)
+            .fold(0, |sides, (_, side)| sides | side)
+    })
+}
+
+pub(crate) fn part1(input: &str) -> usize {
+    visibility(input)
+        .data
+        .into_iter()
+        .filter(|&sides| sides != 0)
         .count()
 }
 
+// viewing distance scan
 //
-// Abandon all hope ye who enter here
-//
+// Looks back along dr from each cell and returns how many trees can be seen
+// before the view is blocked by one at least as tall, or the edge is reached.
+// Trees on the edge see nothing.
+fn view_distance<IX, IY>(im: &Image, dr: (isize, isize), xs: IX, ys: IY) -> Vec<usize>
+where
+    IX: IntoIterator<Item = usize> + Clone,
+    IY: IntoIterator<Item = usize>,
+{
+    let mut dist = vec![0; im.stride * im.shape.1];
+    // where the view from each cell stops
+    let mut block: Vec<_> = (0..im.stride * im.shape.1).collect();
+    for y in ys {
+        let ny = (y as isize + dr.1) as usize;
+        for x in xs.clone() {
+            let nx = (x as isize + dr.0) as usize;
+            let cur = x + y * im.stride;
+            let mut b = nx + ny * im.stride;
+            let mut d = 1;
+            // Anything hidden behind a shorter tree is shorter still, so
+            // jump straight to whatever blocked that tree's view.
+            while dist[b] > 0 && im.data[b] < im.data[cur] {
+                d += dist[b];
+                b = block[b];
+            }
+            dist[cur] = d;
+            block[cur] = b;
+        }
+    }
+    dist
+}
+
+/// The scenic score of every tree.
+pub(crate) fn scenic_scores(input: &str) -> Grid<usize> {
+    let (_rest, im) = parse(input).unwrap();
+
+    let top = view_distance(&im, (0, -1), 0..im.shape.0, 1..im.shape.1);
+    let left = view_distance(&im, (-1, 0), 1..im.shape.0, 0..im.shape.1);
+    let bot = view_distance(&im, (0, 1), 0..im.shape.0, (0..im.shape.1 - 1).rev());
+    let right = view_distance(&im, (1, 0), (0..im.shape.0 - 1).rev(), 0..im.shape.1);
 
-#[derive(Clone)]
-struct Interval {
-    beg: usize,
-    end: usize,
-    h: u8,
-    next: Option<usize>,
+    Grid::from_image(&im, |i| top[i] * left[i] * bot[i] * right[i])
 }
 
-impl Interval {
-    fn length(&self) -> usize {
-        self.end - self.beg
+pub(crate) fn part2(input: &str) -> usize {
+    scenic_scores(input).data.into_iter().max().unwrap()
+}
+
+impl<T: ToPrimitive + Copy> Grid<T> {
+    /// Renders a heatmap. Writes an svg if `filename` ends in `.svg`,
+    /// otherwise a bitmap.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn plot<P: AsRef<Path>>(&self, filename: P, title: &str) -> anyhow::Result<()> {
+        plot::heatmap(filename, title, self.shape, Style::default(), |x, y| {
+            self[(x, y)].to_f64().unwrap()
+        })
     }
 }
 
-fn follow(intervals: &[Option<Interval>], pos: usize, h: u8) -> (usize, usize) {
-    let mut pos = pos;
-    let mut score = 1;
-    while let Some(next) = {
-        let ival = intervals[pos].as_ref().unwrap();
-        if ival.h < h {
-            score = ival.length()
+//
+// Line of sight
+//
+
+/// Range-max lookups in O(1) after O(n log n) setup. `levels[k][i]` is the
+/// max of `values[i..i + 2^k]`.
+#[cfg_attr(not(test), allow(dead_code))]
+struct SparseMax {
+    levels: Vec<Vec<u8>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl SparseMax {
+    fn new(values: Vec<u8>) -> Self {
+        let mut levels = vec![values];
+        let mut width = 1;
+        while 2 * width <= levels[0].len() {
+            let prev = levels.last().unwrap();
+            let next = (0..prev.len() - width)
+                .map(|i| prev[i].max(prev[i + width]))
+                .collect();
+            levels.push(next);
+            width *= 2;
+        }
+        SparseMax { levels }
+    }
+
+    /// Max over `beg..end` or `None` if that's empty.
+    fn max(&self, beg: usize, end: usize) -> Option<u8> {
+        if beg >= end {
+            return None;
         }
-        ival.next
-    } {
-        pos = next;
+        let k = (usize::BITS - 1 - (end - beg).leading_zeros()) as usize;
+        Some(self.levels[k][beg].max(self.levels[k][end - (1 << k)]))
     }
-    return (score, pos);
 }
 
-fn watershed_rows(im: &Image, scores: &mut [usize]) {
-    for y in 0..im.shape.1 {
-        let mut intervals: Vec<Option<Interval>> = vec![None; im.stride * im.shape.1];
-        let mut queued = vec![false; im.stride * im.shape.1];
-        let mut q = BinaryHeap::new();
-        let row = &im.data[y * im.stride..(im.shape.0 + y * im.stride)];
-        let scores_row = &mut scores[y * im.stride..(im.shape.0 + y * im.stride)];
-
-        // Prep boundary
-        intervals[0] = Some(Interval {
-            beg: 0,
-            end: 1,
-            h: row[0],
-            next: None,
-        });
-        intervals[im.shape.0 - 1] = Some(Interval {
-            beg: im.shape.0 - 2,
-            end: im.shape.0 - 1,
-            h: row[im.shape.0 - 1],
-            next: None,
-        });
-        scores_row[0] = 0;
-        scores_row[im.shape.0 - 1] = 0;
-
-        // init w values at min local minima
-        {
-            for (l, r) in (0..im.shape.0 - 2).zip(2..im.shape.0) {
-                if row[l] >= row[l + 1] || row[l + 1] <= row[r] {
-                    q.push(Reverse((row[l + 1], l + 1)));
-                    queued[l + 1] = true;
-                }
-            }
+/// Answers what can be seen from anywhere in the forest.
+///
+/// An observer looks out over trees shorter than their eye height and can see
+/// any tree that pokes up above everything in front of it. So a tree is
+/// visible when every tree strictly between it and the observer is shorter
+/// than either the observer's eye or the tree itself.
+///
+/// Heights are digit values, 0 through 9.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Sight {
+    heights: Grid<u8>,
+    rows: Vec<SparseMax>,
+    cols: Vec<SparseMax>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Sight {
+    pub(crate) fn new(input: &str) -> Self {
+        let (_rest, im) = parse(input).unwrap();
+        let heights = Grid::from_image(&im, |i| im.data[i] - b'0');
+        let (w, h) = heights.shape;
+        let rows = (0..h)
+            .map(|y| SparseMax::new((0..w).map(|x| heights[(x, y)]).collect()))
+            .collect();
+        let cols = (0..w)
+            .map(|x| SparseMax::new((0..h).map(|y| heights[(x, y)]).collect()))
+            .collect();
+        Sight {
+            heights,
+            rows,
+            cols,
         }
+    }
+
+    /// Every tree visible from `at` for an observer whose eye is at height
+    /// `eye`. Looks along the four axes, and along the diagonals too if
+    /// `diagonals` is set. Results are ordered by direction and then by
+    /// distance.
+    pub(crate) fn visible_from(
+        &self,
+        at: (usize, usize),
+        eye: u8,
+        diagonals: bool,
+    ) -> Vec<(usize, usize)> {
+        let axes = [(0, -1), (-1, 0), (0, 1), (1, 0)];
+        let diags = [(-1, -1), (-1, 1), (1, 1), (1, -1)];
+        let ndirs = if diagonals { 8 } else { 4 };
+        let (w, h) = self.heights.shape;
 
-        // watershed
-        while let Some(Reverse((h, i))) = q.pop() {
-            let (left_score, beg) = {
-                // i>0
-                if intervals[i - 1].is_some() {
-                    let (score, j) = follow(&intervals[..], i - 1, h);
-                    let mut interval = intervals[j].as_mut().unwrap();
-                    interval.next = Some(i);
-                    (score, interval.beg)
-                } else {
-                    if i >= 1 && !queued[i - 1] {
-                        q.push(Reverse((row[i - 1], i - 1)));
-                        queued[i - 1] = true;
-                    }
-                    (1, i - 1)
+        let mut out = Vec::new();
+        for (dx, dy) in axes.into_iter().chain(diags).take(ndirs) {
+            let (mut x, mut y) = (at.0 as isize, at.1 as isize);
+            let mut front = None;
+            loop {
+                x += dx;
+                y += dy;
+                if x < 0 || y < 0 || x >= w as isize || y >= h as isize {
+                    break;
                 }
-            };
-            let (right_score, end) = {
-                // i+1<im.shape.0
-                if intervals[i + 1].is_some() {
-                    let (score, j) = follow(&intervals[..], i + 1, h);
-                    let mut interval = intervals[j].as_mut().unwrap();
-                    interval.next = Some(i);
-                    (score, interval.end)
-                } else {
-                    if i >= 1 && !queued[i + 1] {
-                        q.push(Reverse((row[i + 1], i + 1)));
-                        queued[i + 1] = true;
-                    }
-                    (1, i + 1)
+                let tree = self.heights[(x as usize, y as usize)];
+                if front < Some(eye.max(tree)) {
+                    out.push((x as usize, y as usize));
                 }
-            };
-            scores_row[i] *= left_score * right_score;
-            intervals[i] = Some(Interval {
-                beg,
-                end,
-                h,
-                next: None,
-            });
+                front = front.max(Some(tree));
+            }
         }
+        out
+    }
+
+    /// Whether an observer at `a` with their eye at height `eye` can see the
+    /// tree at `b`. `None` if the two aren't on the same row or column.
+    pub(crate) fn can_see(&self, a: (usize, usize), eye: u8, b: (usize, usize)) -> Option<bool> {
+        let (table, a, b) = if a.1 == b.1 {
+            (&self.rows[a.1], a.0, b.0)
+        } else if a.0 == b.0 {
+            (&self.cols[a.0], a.1, b.1)
+        } else {
+            return None;
+        };
+        let front = table.max(a.min(b) + 1, a.max(b));
+        let tree = table.levels[0][b];
+        Some(front < Some(eye.max(tree)))
     }
 }
 
-fn watershed_cols(im: &Image, scores: &mut [usize]) {
-    for x in 0..im.shape.0 {
-        let mut intervals: Vec<Option<Interval>> = vec![None; im.stride * im.shape.1];
-        let mut queued = vec![false; im.stride * im.shape.1];
-        let mut q = BinaryHeap::new();
-
-        let col = |y: usize| im.data[x + y * im.stride];
-
-        // Prep boundary
-        intervals[0] = Some(Interval {
-            beg: 0,
-            end: 1,
-            h: col(0),
-            next: None,
-        });
-        intervals[im.shape.1 - 1] = Some(Interval {
-            beg: im.shape.1 - 2,
-            end: im.shape.1 - 1,
-            h: col(im.shape.1 - 1),
-            next: None,
-        });
-        scores[x] = 0;
-        scores[x + im.stride * (im.shape.1 - 1)] = 0;
-
-        // init w values at min local minima
-        {
-            for (l, r) in (0..im.shape.1 - 2).zip(2..im.shape.1) {
-                if col(l) >= col(l + 1) || col(l + 1) <= col(r) {
-                    q.push(Reverse((col(l + 1), l + 1)));
-                    queued[l + 1] = true;
-                }
-            }
-        }
+#[test]
+fn day8() {
+    assert_eq!(21, part1(include_str!("../assets/day8.test.txt")));
+    assert_eq!(8, part2(include_str!("../assets/day8.test.txt")));
+}
 
-        // watershed
-        while let Some(Reverse((h, i))) = q.pop() {
-            let (left_score, beg) = {
-                // i>0
-                if intervals[i - 1].is_some() {
-                    let (score, j) = follow(&intervals[..], i - 1, h);
-                    let mut interval = intervals[j].as_mut().unwrap();
-                    interval.next = Some(i);
-                    (score, interval.beg)
-                } else {
-                    if i >= 1 && !queued[i - 1] {
-                        q.push(Reverse((col(i - 1), i - 1)));
-                        queued[i - 1] = true;
-                    }
-                    (1, i - 1)
-                }
-            };
-            let (right_score, end) = {
-                // i+1<im.shape.1
-                if intervals[i + 1].is_some() {
-                    let (score, j) = follow(&intervals[..], i + 1, h);
-                    let mut interval = intervals[j].as_mut().unwrap();
-                    interval.next = Some(i);
-                    (score, interval.end)
-                } else {
-                    if i >= 1 && !queued[i + 1] {
-                        q.push(Reverse((col(i + 1), i + 1)));
-                        queued[i + 1] = true;
-                    }
-                    (1, i + 1)
-                }
-            };
-            scores[x + i * im.stride] *= left_score * right_score;
-            intervals[i] = Some(Interval {
-                beg,
-                end,
-                h,
-                next: None,
-            });
+//
+// Reference implementations: look along each direction from every tree.
+//
+
+#[cfg(test)]
+const DIRECTIONS: [(isize, isize); 4] = [(0, -1), (-1, 0), (0, 1), (1, 0)];
+
+/// Trees from (x,y) up to and including the first one at least as tall,
+/// looking along `dr`. The flag is true if the view reaches the edge.
+#[cfg(test)]
+fn view(im: &Image, (x, y): (usize, usize), dr: (isize, isize)) -> (usize, bool) {
+    let h = im[(x, y)];
+    let (mut x, mut y) = (x as isize, y as isize);
+    let mut count = 0;
+    loop {
+        x += dr.0;
+        y += dr.1;
+        if x < 0 || y < 0 || x >= im.shape.0 as isize || y >= im.shape.1 as isize {
+            return (count, true);
+        }
+        count += 1;
+        if im[(x as usize, y as usize)] >= h {
+            return (count, false);
         }
     }
 }
 
-pub(crate) fn part2(input: &str) -> usize {
+#[cfg(test)]
+fn part1_reference(input: &str) -> usize {
     let (_rest, im) = parse(input).unwrap();
+    (0..im.shape.0)
+        .cartesian_product(0..im.shape.1)
+        .filter(|&p| DIRECTIONS.iter().any(|&dr| view(&im, p, dr).1))
+        .count()
+}
+
+#[cfg(test)]
+fn part2_reference(input: &str) -> usize {
+    let (_rest, im) = parse(input).unwrap();
+    (0..im.shape.0)
+        .cartesian_product(0..im.shape.1)
+        .map(|p| DIRECTIONS.iter().map(|&dr| view(&im, p, dr).0).product())
+        .max()
+        .unwrap()
+}
 
-    let mut scores = vec![1; im.stride * im.shape.1];
-    watershed_rows(&im, &mut scores[..]);
-    watershed_cols(&im, &mut scores[..]);
+/// A random `width` by `height` grid of tree heights in `0..nlevels`.
+#[cfg(test)]
+fn forest(seed: u64, width: usize, height: usize, nlevels: u8) -> String {
+    let mut rng = crate::testing::Rng::new(seed);
+    (0..height)
+        .map(|_| {
+            (0..width)
+                .map(|_| (b'0' + rng.below(nlevels as u64) as u8) as char)
+                .collect::<String>()
+        })
+        .join("\n")
+}
 
-    scores.into_iter().max().unwrap()
+#[test]
+fn day8_edge_cases() {
+    let cases = [
+        "5",
+        "12321",
+        "1\n2\n3\n2\n1",
+        "00000\n00000\n00000",
+        "012\n123\n234\n345",
+        "543\n432\n321\n210",
+        "99999\n98789\n99999",
+    ];
+    for input in cases {
+        assert_eq!(part1_reference(input), part1(input), "{input}");
+        assert_eq!(part2_reference(input), part2(input), "{input}");
+    }
 }
 
 #[test]
-fn day8() {
-    assert_eq!(21, part1(include_str!("../assets/day8.test.txt")));
-    assert_eq!(8, part2(include_str!("../assets/day8.test.txt")));
+fn day8_matches_reference() {
+    for seed in 1..3000 {
+        let width = 1 + (seed as usize * 7) % 13;
+        let height = 1 + (seed as usize * 11) % 9;
+        let nlevels = 1 + (seed % 10) as u8;
+        let input = forest(seed, width, height, nlevels);
+        assert_eq!(
+            part1_reference(&input),
+            part1(&input),
+            "seed:{seed}\n{input}"
+        );
+        assert_eq!(
+            part2_reference(&input),
+            part2(&input),
+            "seed:{seed}\n{input}"
+        );
+    }
+}
+
+#[test]
+fn day8_maps() {
+    let input = include_str!("../assets/day8.test.txt");
+
+    let sides = visibility(input);
+    assert_eq!((5, 5), sides.shape);
+    assert_eq!(TOP | LEFT, sides[(1, 1)]);
+    assert_eq!(TOP | RIGHT, sides[(2, 1)]);
+    assert_eq!(0, sides[(3, 1)]);
+    assert_eq!(RIGHT, sides[(1, 2)]);
+    assert_eq!(TOP | LEFT, sides[(0, 0)]);
+    assert_eq!(LEFT | BOTTOM, sides[(0, 4)]);
+
+    let scores = scenic_scores(input);
+    assert_eq!(4, scores[(2, 1)]);
+    assert_eq!(8, scores[(2, 3)]);
+    assert_eq!(0, scores[(0, 2)]);
+}
+
+#[test]
+fn day8_sight() {
+    let sight = Sight::new(include_str!("../assets/day8.test.txt"));
+    assert_eq!(
+        vec![(2, 2), (2, 1), (1, 3), (0, 3), (2, 4), (3, 3), (4, 3)],
+        sight.visible_from((2, 3), 5, false)
+    );
+    assert_eq!(
+        vec![
+            (2, 2),
+            (2, 1),
+            (1, 3),
+            (0, 3),
+            (2, 4),
+            (3, 3),
+            (4, 3),
+            (1, 2),
+            (1, 4),
+            (3, 4),
+            (3, 2),
+            (4, 1)
+        ],
+        sight.visible_from((2, 3), 5, true)
+    );
+    assert_eq!(Some(true), sight.can_see((2, 3), 5, (2, 1)));
+    assert_eq!(Some(false), sight.can_see((2, 3), 5, (2, 0)));
+    assert_eq!(Some(true), sight.can_see((2, 3), 5, (4, 3)));
+    assert_eq!(None, sight.can_see((2, 3), 5, (4, 4)));
+}
+
+#[test]
+fn day8_sight_matches_scan() {
+    for seed in 1..300 {
+        let width = 1 + (seed as usize * 7) % 13;
+        let height = 1 + (seed as usize * 11) % 9;
+        let input = forest(seed, width, height, 1 + (seed % 10) as u8);
+        let sight = Sight::new(&input);
+        for (x, y) in (0..width).cartesian_product(0..height) {
+            let eye = (seed as usize + x + y) as u8 % 10;
+            let visible = sight.visible_from((x, y), eye, false);
+            let aligned = (0..width)
+                .map(|i| (i, y))
+                .chain((0..height).map(|j| (x, j)))
+                .filter(|&b| b != (x, y));
+            for b in aligned {
+                assert_eq!(
+                    Some(visible.contains(&b)),
+                    sight.can_see((x, y), eye, b),
+                    "seed:{seed} a:{:?} eye:{eye} b:{b:?}\n{input}",
+                    (x, y)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn day8_plot() {
+    let dir = crate::testing::scratch_dir("day8_plot");
+    let scores = scenic_scores(include_str!("../assets/day8.test.txt"));
+    for name in ["scores.png", "scores.svg"] {
+        let path = dir.join(name);
+        scores.plot(&path, "Scenic score").unwrap();
+        assert!(path.metadata().unwrap().len() > 0, "{name}");
+    }
 }
@@ -1,13 +1,19 @@
-use std::{collections::HashSet, iter::repeat};
+use std::{
+    collections::HashSet,
+    fmt,
+    iter::{once, repeat_n},
+    path::Path,
+};
 
 use nom::{
     branch::alt,
-    character::complete::{char, digit1, line_ending, space1},
-    combinator::{map, map_res, opt, value},
-    multi::many1,
-    sequence::{separated_pair, terminated},
+    bytes::complete::tag,
+    character::complete::{char, digit1, space1},
+    combinator::{all_consuming, map, map_res, opt, recognize, value},
+    sequence::{pair, separated_pair},
     IResult,
 };
+use plotters::prelude::*;
 
 #[derive(Debug, Clone, Copy)]
 enum Direction {
@@ -15,6 +21,12 @@ enum Direction {
     R,
     U,
     D,
+    UL,
+    UR,
+    DL,
+    DR,
+    /// Relative move given as `dx,dy`.
+    By([i16; 2]),
 }
 
 struct Command {
@@ -22,23 +34,67 @@ struct Command {
     count: usize,
 }
 
-fn parse(input: &str) -> IResult<&str, Vec<Command>> {
-    many1(terminated(
-        map(
-            separated_pair(
-                alt((
-                    value(Direction::L, char('L')),
-                    value(Direction::R, char('R')),
-                    value(Direction::U, char('U')),
-                    value(Direction::D, char('D')),
-                )),
-                space1,
-                map_res(digit1, |d| usize::from_str_radix(d, 10)),
+/// A line of the motion log that couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Error {
+    pub(crate) line: usize,
+    pub(crate) text: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: unknown motion {:?}", self.line, self.text)
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn parse(input: &str) -> Result<Vec<Command>, Error> {
+    fn number(input: &str) -> IResult<&str, i16> {
+        map_res(recognize(pair(opt(char('-')), digit1)), |d: &str| d.parse())(input)
+    }
+
+    fn command(input: &str) -> IResult<&str, Command> {
+        alt((
+            map(
+                separated_pair(
+                    alt((
+                        value(Direction::UL, tag("UL")),
+                        value(Direction::UR, tag("UR")),
+                        value(Direction::DL, tag("DL")),
+                        value(Direction::DR, tag("DR")),
+                        value(Direction::L, char('L')),
+                        value(Direction::R, char('R')),
+                        value(Direction::U, char('U')),
+                        value(Direction::D, char('D')),
+                    )),
+                    space1,
+                    map_res(digit1, |d: &str| d.parse()),
+                ),
+                |(dir, count)| Command { dir, count },
             ),
-            |(dir, count)| Command { dir, count },
-        ),
-        opt(line_ending),
-    ))(input)
+            map(separated_pair(number, char(','), number), |(dx, dy)| {
+                Command {
+                    dir: Direction::By([dx, dy]),
+                    count: 1,
+                }
+            }),
+        ))(input)
+    }
+
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(i, text)| {
+            all_consuming(command)(text.trim_end())
+                .map(|(_, cmd)| cmd)
+                .map_err(|_| Error {
+                    line: i + 1,
+                    text: text.to_owned(),
+                })
+        })
+        .collect()
 }
 
 fn step(dir: Direction) -> [i16; 2] {
@@ -47,6 +103,11 @@ fn step(dir: Direction) -> [i16; 2] {
         Direction::R => [1, 0],
         Direction::U => [0, 1],
         Direction::D => [0, -1],
+        Direction::UL => [-1, 1],
+        Direction::UR => [1, 1],
+        Direction::DL => [-1, -1],
+        Direction::DR => [1, -1],
+        Direction::By(dr) => dr,
     }
 }
 
@@ -54,55 +115,257 @@ fn add(a: [i16; 2], b: [i16; 2]) -> [i16; 2] {
     [a[0] + b[0], a[1] + b[1]]
 }
 
-fn step_tail(dr: [i16; 2], head: [i16; 2]) -> ([i16; 2], [i16; 2]) {
-    let h = add(head, dr);
-    match h {
-        [x, y] if -1 <= x && x <= 1 && -1 <= y && y <= 1 => ([0, 0], h),
-        [-2, -2] => ([-1, -1], [-1, -1]),
-        [-2, 2] => ([-1, 1], [-1, 1]),
-        [2, -2] => ([1, -1], [1, -1]),
-        [2, 2] => ([1, 1], [1, 1]),
-        [-2, y] => ([-1, y], [-1, 0]),
-        [2, y] => ([1, y], [1, 0]),
-        [x, -2] => ([x, -1], [0, -1]),
-        [x, 2] => ([x, 1], [0, 1]),
-        _ => unimplemented!(),
+fn sub(a: [i16; 2], b: [i16; 2]) -> [i16; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn touching(a: [i16; 2], b: [i16; 2]) -> bool {
+    let [dx, dy] = sub(a, b);
+    dx.abs() <= 1 && dy.abs() <= 1
+}
+
+/// Where a knot ends up after the knot ahead of it has moved to `leader`.
+pub(crate) type Follow = fn(knot: [i16; 2], leader: [i16; 2]) -> [i16; 2];
+
+/// The rule from the puzzle: if the knots aren't touching, move one step
+/// towards the leader along each axis where they differ. When the leader is
+/// far away the rope stretches.
+pub(crate) fn pull(knot: [i16; 2], leader: [i16; 2]) -> [i16; 2] {
+    if touching(knot, leader) {
+        knot
+    } else {
+        let [dx, dy] = sub(leader, knot);
+        add(knot, [dx.signum(), dy.signum()])
     }
 }
 
-pub(crate) fn part1(input: &str) -> usize {
-    let (_rest, commands) = parse(input).unwrap();
+/// Keep pulling until the knots are touching again, however far the leader
+/// went. Same as `pull` when the leader only moved one step.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) fn catch_up(knot: [i16; 2], leader: [i16; 2]) -> [i16; 2] {
+    let [dx, dy] = sub(leader, knot);
+    // Each pull closes the gap by one along both axes, so the knot ends up
+    // right next to the leader along whichever axes were furthest apart.
+    let far = dx.abs().max(dy.abs());
+    let offset = |d: i16| if d.abs() == far { d.signum() } else { 0 };
+    if touching(knot, leader) {
+        knot
+    } else {
+        sub(leader, [offset(dx), offset(dy)])
+    }
+}
+
+pub(crate) struct Rope {
+    knots: Vec<[i16; 2]>,
+    follow: Follow,
+}
 
-    commands
+impl Rope {
+    /// A rope with `n` knots all starting at the origin.
+    pub(crate) fn new(n: usize, follow: Follow) -> Self {
+        assert!(n > 0, "a rope needs at least one knot");
+        Rope {
+            knots: vec![[0, 0]; n],
+            follow,
+        }
+    }
+
+    /// Head first.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn knots(&self) -> &[[i16; 2]] {
+        &self.knots
+    }
+
+    pub(crate) fn tail(&self) -> [i16; 2] {
+        *self.knots.last().unwrap()
+    }
+
+    /// Moves the head by `dr` in one go, no matter how far, and then lets
+    /// each knot follow the one ahead of it.
+    pub(crate) fn jump(&mut self, dr: [i16; 2]) {
+        self.knots[0] = add(self.knots[0], dr);
+        for i in 1..self.knots.len() {
+            self.knots[i] = (self.follow)(self.knots[i], self.knots[i - 1]);
+        }
+    }
+}
+
+/// Runs the motions in `input` one step at a time and counts the cells the
+/// tail of an `nknots` long rope visits. Relative moves are made in a single
+/// jump.
+pub(crate) fn tail_visits(input: &str, nknots: usize, follow: Follow) -> Result<usize, Error> {
+    let commands = parse(input)?;
+    let mut rope = Rope::new(nknots, follow);
+
+    Ok(commands
         .into_iter()
-        .flat_map(|c| repeat(c.dir).take(c.count))
-        .scan(([0, 0], [0, 0]), |(t, h), dir| {
-            let (dt, newh) = step_tail(step(dir), *h);
-            *t = add(*t, dt);
-            *h = newh;
-            Some(*t)
+        .flat_map(|c| repeat_n(c.dir, c.count))
+        .map(|dir| {
+            rope.jump(step(dir));
+            rope.tail()
         })
+        .chain(once([0, 0]))
         .collect::<HashSet<_>>()
-        .len()
+        .len())
 }
 
-pub(crate) fn part2(input: &str) -> usize {
-    let (_rest, commands) = parse(input).unwrap();
+/// Every position of every knot while a rope follows a motion log.
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) struct Trail {
+    /// Knot positions after each step, head first. The first frame is the
+    /// starting position.
+    pub(crate) frames: Vec<Vec<[i16; 2]>>,
+}
 
-    commands
-        .into_iter()
-        .flat_map(|c| repeat(c.dir).take(c.count))
-        .scan((0, [[0, 0]; 10]), |(t, knots), dir| {
-            let mut dr = step(dir);
-            for i in 0..9 {
-                (dr, knots[i]) = step_tail(dr, knots[i]);
-            }
-            knots[9] = add(knots[9], dr);
-            *t += 1;
-            Some(knots[9])
+#[cfg_attr(not(test), allow(dead_code))]
+impl Trail {
+    pub(crate) fn record(input: &str, nknots: usize, follow: Follow) -> Result<Trail, Error> {
+        let commands = parse(input)?;
+        let mut rope = Rope::new(nknots, follow);
+        let frames = once(rope.knots().to_vec())
+            .chain(
+                commands
+                    .into_iter()
+                    .flat_map(|c| repeat_n(c.dir, c.count))
+                    .map(|dir| {
+                        rope.jump(step(dir));
+                        rope.knots().to_vec()
+                    }),
+            )
+            .collect();
+        Ok(Trail { frames })
+    }
+
+    /// Cells the `knot`'th knot has been in. The head is knot 0.
+    pub(crate) fn visited(&self, knot: usize) -> HashSet<[i16; 2]> {
+        self.frames.iter().map(|f| f[knot]).collect()
+    }
+
+    /// Smallest and largest coordinates any knot reached.
+    pub(crate) fn bounds(&self) -> ([i16; 2], [i16; 2]) {
+        self.frames
+            .iter()
+            .flatten()
+            .fold(([0, 0], [0, 0]), |([x0, y0], [x1, y1]), &[x, y]| {
+                ([x0.min(x), y0.min(y)], [x1.max(x), y1.max(y)])
+            })
+    }
+
+    /// Draws the trail's extent with `cell` picking the character at each
+    /// position. Up is up.
+    fn draw(&self, cell: impl Fn([i16; 2]) -> char) -> String {
+        let ([x0, y0], [x1, y1]) = self.bounds();
+        let mut out = String::new();
+        for y in (y0..=y1).rev() {
+            out.extend((x0..=x1).map(|x| cell([x, y])));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The cells a knot visited drawn like the puzzle: `#` where it's been,
+    /// `s` at the start.
+    pub(crate) fn visited_map(&self, knot: usize) -> String {
+        let visited = self.visited(knot);
+        self.draw(|p| match p {
+            [0, 0] => 's',
+            p if visited.contains(&p) => '#',
+            _ => '.',
         })
-        .collect::<HashSet<_>>()
-        .len()
+    }
+
+    /// A snapshot of the rope after `step` steps. Each knot is drawn with its
+    /// label, e.g. `HT` or `H123456789`. Knots past the end of `labels` are
+    /// drawn as the last digit of their index. When knots overlap the one
+    /// closest to the head is shown.
+    pub(crate) fn frame_map(&self, step: usize, labels: &[char]) -> String {
+        let knots = &self.frames[step];
+        self.draw(|p| match knots.iter().position(|&k| k == p) {
+            Some(i) => labels
+                .get(i)
+                .copied()
+                .unwrap_or_else(|| char::from_digit(i as u32 % 10, 10).unwrap()),
+            None if p == [0, 0] => 's',
+            None => '.',
+        })
+    }
+
+    fn color(&self, knot: usize) -> HSLColor {
+        let n = self.frames[0].len();
+        HSLColor(240.0 / 360.0 * knot as f64 / n as f64, 0.7, 0.5)
+    }
+
+    /// Renders the cells every knot visited, each in its own color with the
+    /// tail on top.
+    pub(crate) fn plot<P: AsRef<Path>>(&self, filename: P, title: &str) -> anyhow::Result<()> {
+        let ([x0, y0], [x1, y1]) = self.bounds();
+        let root = BitMapBackend::new(&filename, (1024, 768)).into_drawing_area();
+
+        root.fill(&WHITE)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(title, ("sans-serif", 30))
+            .margin(5)
+            .x_label_area_size(40)
+            .y_label_area_size(40)
+            .build_cartesian_2d(x0 as i32..x1 as i32 + 1, y0 as i32..y1 as i32 + 1)?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .label_style(("sans-serif", 20))
+            .draw()?;
+
+        for knot in 0..self.frames[0].len() {
+            let color = self.color(knot);
+            chart.draw_series(self.visited(knot).into_iter().map(|[x, y]| {
+                let (x, y) = (x as i32, y as i32);
+                Rectangle::new([(x, y), (x + 1, y + 1)], color.filled())
+            }))?;
+        }
+
+        root.present()?;
+        Ok(())
+    }
+
+    /// Writes a gif of the rope moving, one frame per `stride` steps with
+    /// `delay` ms between frames.
+    pub(crate) fn animate<P: AsRef<Path>>(
+        &self,
+        filename: P,
+        delay: u32,
+        stride: usize,
+    ) -> anyhow::Result<()> {
+        let ([x0, y0], [x1, y1]) = self.bounds();
+        let root = BitMapBackend::gif(&filename, (800, 800), delay)?.into_drawing_area();
+
+        for (i, knots) in self.frames.iter().enumerate().step_by(stride.max(1)) {
+            root.fill(&WHITE)?;
+
+            let mut chart = ChartBuilder::on(&root)
+                .caption(format!("step {i}"), ("sans-serif", 20))
+                .margin(5)
+                .build_cartesian_2d(x0 as i32..x1 as i32 + 1, y0 as i32..y1 as i32 + 1)?;
+
+            // Tail first so the head ends up on top.
+            chart.draw_series(knots.iter().enumerate().rev().map(|(knot, &[x, y])| {
+                let (x, y) = (x as i32, y as i32);
+                Rectangle::new([(x, y), (x + 1, y + 1)], self.color(knot).filled())
+            }))?;
+
+            root.present()?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn part1(input: &str) -> usize {
+    tail_visits(input, 2, pull).unwrap()
+}
+
+pub(crate) fn part2(input: &str) -> usize {
+    tail_visits(input, 10, pull).unwrap()
 }
 
 #[test]
@@ -111,3 +374,108 @@ fn day9() {
     assert_eq!(1, part2(include_str!("../assets/day9.test.txt")));
     assert_eq!(36, part2(include_str!("../assets/day9.test.2.txt")));
 }
+
+#[test]
+fn day9_rope() {
+    // catch_up agrees with pull for single steps
+    let input = include_str!("../assets/day9.test.2.txt");
+    assert_eq!(Ok(36), tail_visits(input, 10, catch_up));
+
+    // Big jumps: pull stretches the rope, catch_up keeps it together.
+    let mut rope = Rope::new(3, pull);
+    rope.jump([5, 2]);
+    assert_eq!(&[[5, 2], [1, 1], [0, 0]], rope.knots());
+
+    let mut rope = Rope::new(3, catch_up);
+    rope.jump([5, 2]);
+    assert_eq!(&[[5, 2], [4, 2], [3, 2]], rope.knots());
+    rope.jump([-10, -10]);
+    assert_eq!(&[[-5, -8], [-5, -7], [-5, -6]], rope.knots());
+    rope.jump([0, 3]);
+    assert_eq!(&[[-5, -5], [-5, -6], [-5, -6]], rope.knots());
+    rope.jump([2, 2]);
+    assert_eq!(&[[-3, -3], [-3, -4], [-4, -5]], rope.knots());
+}
+
+#[test]
+fn day9_motions() {
+    // Diagonal head moves pull the knots along the diagonal.
+    assert_eq!(Ok(4), tail_visits("UR 4\n", 2, pull));
+    assert_eq!(Ok(2), tail_visits("UR 4\n", 4, pull));
+    assert_eq!(Ok(6), tail_visits("R 2\nUL 3\nDL 3\nDR 1", 2, pull));
+
+    // A jump counts as one step.
+    assert_eq!(Ok(2), tail_visits("5,-3\n", 2, pull));
+    assert_eq!(Ok(2), tail_visits("5,-3\n", 2, catch_up));
+    assert_eq!(Ok(2), tail_visits("1,1\n2,-1\n\n-3,0\n", 2, pull));
+
+    assert_eq!(
+        Err(Error {
+            line: 3,
+            text: "X 2".to_owned()
+        }),
+        tail_visits("R 1\n\nX 2\nL 2\n", 2, pull)
+    );
+    assert!(tail_visits("R 1\nR -2\n", 2, pull).is_err());
+}
+
+#[test]
+fn day9_trail() {
+    let trail = Trail::record(include_str!("../assets/day9.test.txt"), 2, pull).unwrap();
+    assert_eq!(
+        "\
+..##..
+...##.
+.####.
+....#.
+s###..
+",
+        trail.visited_map(1)
+    );
+    assert_eq!(
+        "\
+......
+......
+.TH...
+......
+s.....
+",
+        trail.frame_map(trail.frames.len() - 1, &['H', 'T'])
+    );
+    assert_eq!(
+        "\
+....H.
+....1.
+..432.
+.5....
+6.....
+",
+        Trail::record(include_str!("../assets/day9.test.txt"), 10, pull)
+            .unwrap()
+            .frame_map(8, &['H', '1', '2', '3', '4', '5', '6', '7', '8', '9'])
+    );
+    assert_eq!(
+        "\
+....H.
+....1.
+..432.
+.5....
+6.....
+",
+        Trail::record(include_str!("../assets/day9.test.txt"), 10, pull)
+            .unwrap()
+            .frame_map(8, &['H'])
+    );
+}
+
+#[test]
+fn day9_plot() {
+    let dir = crate::testing::scratch_dir("day9_plot");
+    let trail = Trail::record(include_str!("../assets/day9.test.2.txt"), 10, pull).unwrap();
+
+    trail.plot(dir.join("trail.png"), "Rope").unwrap();
+    trail.animate(dir.join("trail.gif"), 50, 40).unwrap();
+    for name in ["trail.png", "trail.gif"] {
+        assert!(dir.join(name).metadata().unwrap().len() > 0, "{name}");
+    }
+}
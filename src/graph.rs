@@ -0,0 +1,287 @@
+//! Shortest paths over graphs described by a neighbor function.
+//!
+//! Nodes are numbered `0..n`. Unweighted searches take a function listing
+//! the nodes one step away from a node; weighted ones list `(node, cost)`.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+};
+
+/// Result of a single or multi-source search.
+#[derive(Debug, Clone)]
+pub(crate) struct Paths {
+    dist: Vec<Option<usize>>,
+    prev: Vec<Option<usize>>,
+}
+
+impl Paths {
+    fn new(n: usize) -> Self {
+        Paths {
+            dist: vec![None; n],
+            prev: vec![None; n],
+        }
+    }
+
+    /// Cost of the cheapest path from any source, if there is one.
+    pub(crate) fn dist(&self, node: usize) -> Option<usize> {
+        self.dist[node]
+    }
+
+    /// Every node's cost, indexed by node.
+    pub(crate) fn dists(&self) -> &[Option<usize>] {
+        &self.dist
+    }
+
+    /// A cheapest path from a source to `node`, both included.
+    pub(crate) fn path_to(&self, node: usize) -> Option<Vec<usize>> {
+        self.dist[node]?;
+        let mut path = vec![node];
+        while let Some(p) = self.prev[*path.last().unwrap()] {
+            path.push(p);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// Breadth first search from every node in `sources` at once.
+pub(crate) fn bfs<N, I>(
+    n: usize,
+    sources: impl IntoIterator<Item = usize>,
+    mut neighbors: N,
+) -> Paths
+where
+    N: FnMut(usize) -> I,
+    I: IntoIterator<Item = usize>,
+{
+    let mut paths = Paths::new(n);
+    let mut q = VecDeque::new();
+    for s in sources {
+        if paths.dist[s].is_none() {
+            paths.dist[s] = Some(0);
+            q.push_back(s);
+        }
+    }
+    while let Some(cur) = q.pop_front() {
+        let d = paths.dist[cur].unwrap() + 1;
+        for next in neighbors(cur) {
+            if paths.dist[next].is_none() {
+                paths.dist[next] = Some(d);
+                paths.prev[next] = Some(cur);
+                q.push_back(next);
+            }
+        }
+    }
+    paths
+}
+
+/// Dijkstra's algorithm from every node in `sources` at once.
+pub(crate) fn dijkstra<N, I>(
+    n: usize,
+    sources: impl IntoIterator<Item = usize>,
+    mut neighbors: N,
+) -> Paths
+where
+    N: FnMut(usize) -> I,
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut paths = Paths::new(n);
+    let mut q = BinaryHeap::new();
+    for s in sources {
+        paths.dist[s] = Some(0);
+        q.push(Reverse((0, s)));
+    }
+    while let Some(Reverse((d, cur))) = q.pop() {
+        if paths.dist[cur] < Some(d) {
+            continue; // stale
+        }
+        for (next, cost) in neighbors(cur) {
+            let d = d + cost;
+            if paths.dist[next].is_none_or(|old| d < old) {
+                paths.dist[next] = Some(d);
+                paths.prev[next] = Some(cur);
+                q.push(Reverse((d, next)));
+            }
+        }
+    }
+    paths
+}
+
+/// A* search from `source` to `target`. `heuristic` must never overestimate
+/// the remaining cost. Returns the cost and the path.
+pub(crate) fn astar<N, I>(
+    n: usize,
+    source: usize,
+    target: usize,
+    mut neighbors: N,
+    heuristic: impl Fn(usize) -> usize,
+) -> Option<(usize, Vec<usize>)>
+where
+    N: FnMut(usize) -> I,
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut paths = Paths::new(n);
+    let mut q = BinaryHeap::new();
+    paths.dist[source] = Some(0);
+    q.push(Reverse((heuristic(source), 0, source)));
+    while let Some(Reverse((_, d, cur))) = q.pop() {
+        if cur == target {
+            return Some((d, paths.path_to(target).unwrap()));
+        }
+        if paths.dist[cur] < Some(d) {
+            continue; // stale
+        }
+        for (next, cost) in neighbors(cur) {
+            let d = d + cost;
+            if paths.dist[next].is_none_or(|old| d < old) {
+                paths.dist[next] = Some(d);
+                paths.prev[next] = Some(cur);
+                q.push(Reverse((d + heuristic(next), d, next)));
+            }
+        }
+    }
+    None
+}
+
+/// Heuristic for grids stored row by row, `stride` cells to a row, where
+/// every step costs at least 1 and moves one cell across or down.
+pub(crate) fn manhattan(stride: usize, target: usize) -> impl Fn(usize) -> usize {
+    move |i| (i % stride).abs_diff(target % stride) + (i / stride).abs_diff(target / stride)
+}
+
+/// Shortest paths between every pair of nodes.
+#[derive(Debug, Clone)]
+pub(crate) struct AllPairs {
+    n: usize,
+    dist: Vec<Option<usize>>,
+    #[cfg_attr(not(test), allow(dead_code))]
+    next: Vec<Option<usize>>,
+}
+
+impl AllPairs {
+    pub(crate) fn dist(&self, src: usize, dst: usize) -> Option<usize> {
+        self.dist[dst + src * self.n]
+    }
+
+    /// The node after `src` on a shortest path to `dst`.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn next(&self, src: usize, dst: usize) -> Option<usize> {
+        self.next[dst + src * self.n]
+    }
+
+    /// A shortest path from `src` to `dst`, both included.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub(crate) fn path(&self, src: usize, dst: usize) -> Option<Vec<usize>> {
+        self.dist(src, dst)?;
+        let mut path = vec![src];
+        while *path.last().unwrap() != dst {
+            path.push(self.next(*path.last().unwrap(), dst).unwrap());
+        }
+        Some(path)
+    }
+}
+
+/// Floyd-Warshall.
+pub(crate) fn all_pairs<N, I>(n: usize, mut neighbors: N) -> AllPairs
+where
+    N: FnMut(usize) -> I,
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut dist = vec![None; n * n];
+    let mut next = vec![None; n * n];
+    for i in 0..n {
+        dist[i + i * n] = Some(0);
+        next[i + i * n] = Some(i);
+        for (j, cost) in neighbors(i) {
+            if dist[j + i * n].is_none_or(|old| cost < old) {
+                dist[j + i * n] = Some(cost);
+                next[j + i * n] = Some(j);
+            }
+        }
+    }
+    for k in 0..n {
+        for src in 0..n {
+            let Some(a) = dist[k + src * n] else {
+                continue;
+            };
+            for dst in 0..n {
+                if let Some(b) = dist[dst + k * n] {
+                    if dist[dst + src * n].is_none_or(|old| a + b < old) {
+                        dist[dst + src * n] = Some(a + b);
+                        next[dst + src * n] = next[k + src * n];
+                    }
+                }
+            }
+        }
+    }
+    AllPairs { n, dist, next }
+}
+
+#[test]
+fn graph() {
+    // A ring 0-1-2-3 where 3-0 costs 5 and the rest 1, with 4 hanging off
+    // 3 and 5 on its own.
+    let edges = [
+        vec![(1, 1), (3, 5)],
+        vec![(0, 1), (2, 1)],
+        vec![(1, 1), (3, 1)],
+        vec![(0, 5), (2, 1), (4, 1)],
+        vec![(3, 1)],
+        vec![],
+    ];
+    let weighted = |i: usize| edges[i].clone();
+    let unweighted = |i: usize| edges[i].iter().map(|&(j, _)| j).collect::<Vec<_>>();
+
+    let hops = bfs(6, [0], unweighted);
+    assert_eq!(Some(2), hops.dist(4));
+    assert_eq!(Some(vec![0, 3, 4]), hops.path_to(4));
+    assert_eq!(None, hops.dist(5));
+    assert_eq!(None, hops.path_to(5));
+
+    let cost = dijkstra(6, [0], weighted);
+    assert_eq!(Some(4), cost.dist(4));
+    assert_eq!(Some(vec![0, 1, 2, 3, 4]), cost.path_to(4));
+
+    let from_both = dijkstra(6, [0, 4], weighted);
+    assert_eq!(
+        &[Some(0), Some(1), Some(2), Some(1), Some(0), None],
+        from_both.dists()
+    );
+    assert_eq!(Some(vec![4, 3]), from_both.path_to(3));
+
+    assert_eq!(
+        Some((4, vec![0, 1, 2, 3, 4])),
+        astar(6, 0, 4, weighted, |_| 0)
+    );
+    assert_eq!(None, astar(6, 0, 5, weighted, |_| 0));
+
+    let all = all_pairs(6, weighted);
+    for src in 0..6 {
+        let one = dijkstra(6, [src], weighted);
+        for dst in 0..6 {
+            assert_eq!(one.dist(dst), all.dist(src, dst));
+        }
+    }
+    assert_eq!(Some(vec![4, 3, 2, 1, 0]), all.path(4, 0));
+    assert_eq!(Some(vec![5]), all.path(5, 5));
+    assert_eq!(None, all.path(5, 0));
+
+    // On an open 10x10 grid A* agrees with the heuristic exactly.
+    let grid = |i: usize| {
+        let (x, y) = (i % 10, i / 10);
+        [
+            (x > 0, i.wrapping_sub(1)),
+            (x < 9, i + 1),
+            (y > 0, i.wrapping_sub(10)),
+            (y < 9, i + 10),
+        ]
+        .into_iter()
+        .filter(|&(ok, _)| ok)
+        .map(|(_, j)| (j, 1))
+        .collect::<Vec<_>>()
+    };
+    let (d, path) = astar(100, 0, 99, grid, manhattan(10, 99)).unwrap();
+    assert_eq!(18, d);
+    assert_eq!(19, path.len());
+}
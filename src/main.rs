@@ -1,20 +1,25 @@
 // mod day1;
-// mod day10;
-// mod day11;
-// mod day12;
-// mod day13;
-// mod day14;
+mod day10;
+mod day11;
+mod day12;
+mod day13;
+mod day14;
 // mod day15;
+mod day16;
+mod day17;
 // mod day2;
 // mod day3;
 // mod day4;
 // mod day5;
-// mod day6;
-// mod day7;
-// mod day8;
-// mod day9;
-// mod day16;
-mod day17;
+mod day6;
+mod day7;
+mod day8;
+mod day9;
+mod graph;
+mod ocr;
+mod plot;
+#[cfg(test)]
+mod testing;
 
 macro_rules! problems {
     ()=>{};
@@ -43,17 +48,17 @@ fn main() {
         // day3 part1 part2,
         // day4 part1 part2,
         // day5 part1 part2,
-        // day6 part1 part2,
-        // day7 part1 part2,
-        // day8 part1 part2,
-        // day9 part1 part2,
-        // day10 part1 part2,
-        // day11 part1 part2,
-        // day12 part1 part2,
-        // day13 part1 part2,
-        // day14 part1 part2,
+        day6 part1 part2,
+        day7 part1 part2,
+        day8 part1 part2,
+        day9 part1 part2,
+        day10 part1 part2,
+        day11 part1 part2,
+        day12 part1 part2,
+        day13 part1 part2,
+        day14 part1 part2,
         // day15 part1 part2, // slow in debug
-        // day16 part1
+        day16 part1,
         day17 part1
     );
 }
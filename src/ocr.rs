@@ -0,0 +1,103 @@
+//! Reads the block letters some puzzles draw as their answer.
+//!
+//! Letters are 4 pixels wide and 6 tall, with one blank column between them.
+
+use std::fmt;
+
+pub(crate) const WIDTH: usize = 4;
+pub(crate) const HEIGHT: usize = 6;
+
+#[rustfmt::skip]
+const FONT: &[(char, [&str; HEIGHT])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// A letter-sized block that isn't in the font.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Unknown {
+    /// Which letter it was, counting from 0.
+    pub(crate) index: usize,
+    /// Column of its left edge.
+    pub(crate) x: usize,
+}
+
+impl fmt::Display for Unknown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown glyph #{} at x={}", self.index, self.x)
+    }
+}
+
+impl std::error::Error for Unknown {}
+
+/// Reads the letters along the top 6 rows of an image `width` pixels wide.
+/// `lit(x, y)` says whether a pixel is on. Blank blocks read as spaces and
+/// any trailing ones are dropped.
+///
+/// Fails with every block that couldn't be read.
+pub(crate) fn decode(
+    width: usize,
+    lit: impl Fn(usize, usize) -> bool,
+) -> Result<String, Vec<Unknown>> {
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+    for (index, x0) in (0..width).step_by(WIDTH + 1).enumerate() {
+        let glyph = |x: usize, y: usize| x0 + x < width && lit(x0 + x, y);
+        let blank = (0..HEIGHT).all(|y| (0..WIDTH).all(|x| !glyph(x, y)));
+        let found = FONT.iter().find(|(_, rows)| {
+            rows.iter().enumerate().all(|(y, row)| {
+                row.bytes()
+                    .enumerate()
+                    .all(|(x, c)| (c == b'#') == glyph(x, y))
+            })
+        });
+        match found {
+            Some(&(c, _)) => text.push(c),
+            None if blank => text.push(' '),
+            None => unknown.push(Unknown { index, x: x0 }),
+        }
+    }
+    if unknown.is_empty() {
+        Ok(text.trim_end().to_owned())
+    } else {
+        Err(unknown)
+    }
+}
+
+#[test]
+fn ocr() {
+    // Draw every letter in the font side by side, then read them back.
+    let letters: Vec<_> = FONT.iter().collect();
+    let width = letters.len() * (WIDTH + 1);
+    let lit = |x: usize, y: usize| {
+        let (_, rows) = letters[x / (WIDTH + 1)];
+        x % (WIDTH + 1) < WIDTH && rows[y].as_bytes()[x % (WIDTH + 1)] == b'#'
+    };
+    assert_eq!(Ok("ABCEFGHJKLOPRSUZ".to_owned()), decode(width, lit));
+
+    // Leading blanks are spaces, trailing ones are dropped.
+    assert_eq!(
+        Ok(" A".to_owned()),
+        decode(9, |x, y| x >= 5 && lit(x - 5, y))
+    );
+    assert_eq!(Ok(String::new()), decode(40, |_, _| false));
+
+    assert_eq!(
+        Err(vec![Unknown { index: 1, x: 5 }]),
+        decode(10, |x, y| if x < 5 { lit(x, y) } else { (x + y) % 2 == 0 })
+    );
+}
@@ -0,0 +1,103 @@
+//! Plots shared between days.
+
+use std::path::Path;
+
+use itertools::Itertools;
+use plotters::{coord::Shift, prelude::*};
+
+/// How a heatmap is drawn.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Style {
+    /// The value drawn in red, or `None` for the largest one.
+    pub(crate) scale: Option<f64>,
+    /// Label every row and column rather than a few.
+    pub(crate) label_cells: bool,
+}
+
+/// Renders a `width` by `height` heatmap of `value(x, y)` with `(0, 0)` at
+/// the top left, going from blue at zero to red at `style.scale`. Writes an
+/// svg if `filename` ends in `.svg`, otherwise a bitmap.
+pub(crate) fn heatmap<P: AsRef<Path>>(
+    filename: P,
+    title: &str,
+    shape: (usize, usize),
+    style: Style,
+    value: impl Fn(usize, usize) -> f64,
+) -> anyhow::Result<()> {
+    let path = filename.as_ref();
+    if path.extension() == Some("svg".as_ref()) {
+        let root = SVGBackend::new(path, (1024, 768)).into_drawing_area();
+        draw_heatmap(root, title, shape, style, value)
+    } else {
+        let root = BitMapBackend::new(path, (1024, 768)).into_drawing_area();
+        draw_heatmap(root, title, shape, style, value)
+    }
+}
+
+fn draw_heatmap<DB>(
+    root: DrawingArea<DB, Shift>,
+    title: &str,
+    (w, h): (usize, usize),
+    style: Style,
+    value: impl Fn(usize, usize) -> f64,
+) -> anyhow::Result<()>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 30))
+        .margin(5)
+        .top_x_label_area_size(40)
+        .y_label_area_size(40)
+        .build_cartesian_2d(0..w as i32, h as i32..0)?;
+
+    let mut mesh = chart.configure_mesh();
+    mesh.max_light_lines(4)
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .label_style(("sans-serif", 20));
+    if style.label_cells {
+        mesh.x_labels(w)
+            .y_labels(h)
+            .x_label_offset(35)
+            .y_label_offset(25);
+    }
+    mesh.draw()?;
+
+    let mx = style.scale.unwrap_or_else(|| {
+        (0..w)
+            .cartesian_product(0..h)
+            .map(|(x, y)| value(x, y))
+            .fold(0.0, f64::max)
+            .max(1.0)
+    });
+
+    chart.draw_series((0..w).cartesian_product(0..h).map(|(x, y)| {
+        let v = value(x, y) / mx;
+        let (x, y) = (x as i32, y as i32);
+        Rectangle::new(
+            [(x, y), (x + 1, y + 1)],
+            HSLColor(240.0 / 360.0 - 240.0 / 360.0 * v, 0.7, 0.1 + 0.4 * v).filled(),
+        )
+    }))?;
+
+    root.present()?;
+    Ok(())
+}
+
+#[test]
+fn plot() {
+    let dir = crate::testing::scratch_dir("plot");
+    let fixed = Style {
+        scale: Some(20.0),
+        label_cells: true,
+    };
+    for (name, style) in [("auto.png", Style::default()), ("fixed.svg", fixed)] {
+        let path = dir.join(name);
+        heatmap(&path, name, (4, 3), style, |x, y| (x * y) as f64).unwrap();
+        assert!(path.metadata().unwrap().len() > 0, "{name}");
+    }
+}